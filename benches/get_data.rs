@@ -0,0 +1,135 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use interprether::cache::{parse_members, TransactionCache};
+use interprether::chain::Chain;
+use interprether::codec::{Encoding, Format};
+use interprether::schema;
+use interprether::transaction::Transaction;
+use std::sync::Arc;
+
+const SECONDS_IN_DAY: u64 = 86400;
+const BLOCK_TIME: u64 = 12;
+const TXS_PER_BLOCK: u64 = 5;
+const CONCURRENT_REQUESTS: usize = 32;
+
+// One member per transaction over a full day, like the scanner writes them
+fn members(format: &Format) -> Vec<(Vec<u8>, u64)> {
+    (0..SECONDS_IN_DAY / BLOCK_TIME)
+        .flat_map(|block| {
+            let timestamp = block * BLOCK_TIME;
+            (0..TXS_PER_BLOCK).map(move |i| {
+                let transaction = Transaction {
+                    hash: format!("0x{:064x}", timestamp * TXS_PER_BLOCK + i),
+                    message: format!("Message number {} in block {}", i, block),
+                    timestamp,
+                    from: Some(format!("0x{:040x}", i)),
+                    to: Some(format!("0x{:040x}", block)),
+                    ..Default::default()
+                };
+
+                (schema::encode(&transaction, format).unwrap(), timestamp)
+            })
+        })
+        .collect()
+}
+
+fn formats() -> Vec<(&'static str, Format)> {
+    vec![
+        ("json", Format::default()),
+        (
+            "msgpack",
+            Format {
+                encoding: Encoding::MessagePack,
+                compression: None,
+            },
+        ),
+        (
+            "msgpack+zstd",
+            Format {
+                encoding: Encoding::MessagePack,
+                compression: Some(3),
+            },
+        ),
+    ]
+}
+
+fn bench_get_data(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    // Redis returns members newest first
+    let members = members(&Format::default());
+    let raw: Arc<Vec<Vec<u8>>> = Arc::new(members.iter().rev().map(|(m, _)| m.clone()).collect());
+
+    let cache = Arc::new(TransactionCache::new(Chain::default()));
+    runtime.block_on(cache.load(members)).unwrap();
+
+    let mut group = c.benchmark_group("get_data");
+    group.sample_size(10);
+
+    for concurrency in [1, CONCURRENT_REQUESTS] {
+        // Every request parses the whole window again
+        group.bench_with_input(BenchmarkId::new("parse", concurrency), &concurrency, |b, &n| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let handles: Vec<_> = (0..n)
+                        .map(|_| {
+                            let raw = raw.clone();
+                            tokio::spawn(async move { parse_members(&raw).len() })
+                        })
+                        .collect();
+
+                    for handle in handles {
+                        handle.await.unwrap();
+                    }
+                })
+            })
+        });
+
+        // Every request reads from the shared, already parsed window
+        group.bench_with_input(BenchmarkId::new("cache", concurrency), &concurrency, |b, &n| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let handles: Vec<_> = (0..n)
+                        .map(|_| {
+                            let cache = cache.clone();
+                            tokio::spawn(async move { cache.window(SECONDS_IN_DAY, 0).await.len() })
+                        })
+                        .collect();
+
+                    for handle in handles {
+                        handle.await.unwrap();
+                    }
+                })
+            })
+        });
+    }
+
+    group.finish();
+}
+
+// Memory held by Redis for a day of transactions and time to read it back, which is what
+// `/transactions` pays whenever the cache reloads the window, in every storage format
+fn bench_storage_formats(c: &mut Criterion) {
+    let mut group = c.benchmark_group("storage_format");
+    group.sample_size(10);
+
+    for (name, format) in formats() {
+        let raw: Vec<Vec<u8>> = members(&format).into_iter().rev().map(|(m, _)| m).collect();
+        let bytes: usize = raw.iter().map(Vec::len).sum();
+        println!(
+            "storage_format/{}: {} transactions, {} bytes, {} bytes per transaction",
+            name,
+            raw.len(),
+            bytes,
+            bytes / raw.len()
+        );
+
+        group.bench_function(BenchmarkId::new("parse", name), |b| {
+            b.iter(|| parse_members(&raw).len())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_get_data, bench_storage_formats);
+criterion_main!(benches);
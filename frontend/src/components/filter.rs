@@ -14,6 +14,8 @@ pub enum TransactionFilterField {
     From,
     To,
     Message,
+    Kind,
+    Lang,
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
@@ -89,6 +91,8 @@ impl Filter {
             TransactionFilterField::From => "from",
             TransactionFilterField::To => "to",
             TransactionFilterField::Message => "message",
+            TransactionFilterField::Kind => "kind",
+            TransactionFilterField::Lang => "lang",
         };
 
         html! {
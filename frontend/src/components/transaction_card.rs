@@ -1,6 +1,7 @@
 use crate::components::filter::{TransactionFilter, TransactionFilterField, TransactionFilterOperation};
 use crate::components::transaction_message::TransactionMessage;
-use crate::model::Transaction;
+use crate::data_uri;
+use crate::model::{language_name, Transaction};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use std::sync::Arc;
 use yew::classes;
@@ -9,29 +10,50 @@ use yew::prelude::*;
 #[derive(Clone, Debug, PartialEq, Properties)]
 pub struct Props {
     pub tx: Transaction,
+    pub explorer_url: String,
     pub now: u64,
     pub text_filter: Arc<Option<String>>,
     pub add_filter: Callback<TransactionFilter>,
 }
 
+pub enum Msg {
+    Reveal,
+}
+
 pub struct TransactionCard {
     props: Props,
+    link: ComponentLink<Self>,
+    // Whether the flagged message was clicked to be shown
+    revealed: bool,
 }
 
 impl Component for TransactionCard {
-    type Message = ();
+    type Message = Msg;
     type Properties = Props;
 
-    fn create(props: Self::Properties, _link: ComponentLink<Self>) -> Self {
-        Self { props }
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        Self {
+            props,
+            link,
+            revealed: false,
+        }
     }
 
-    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
-        unimplemented!()
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Msg::Reveal => {
+                self.revealed = true;
+                true
+            }
+        }
     }
 
     fn change(&mut self, props: Self::Properties) -> ShouldRender {
         if self.props != props {
+            // Cards are reused while scrolling, a new message starts blurred again
+            if self.props.tx.key() != props.tx.key() {
+                self.revealed = false;
+            }
             self.props = props;
             true
         } else {
@@ -45,7 +67,7 @@ impl Component for TransactionCard {
             _ => None,
         };
 
-        let link = format!("https://etherscan.io/tx/{}", self.props.tx.hash);
+        let link = format!("{}/tx/{}", self.props.explorer_url, self.props.tx.hash);
 
         // Create human-readable time
         let duration = chrono::Duration::seconds(self.props.tx.timestamp as i64 - self.props.now as i64);
@@ -59,14 +81,101 @@ impl Component for TransactionCard {
         let message = self.props.tx.message.clone();
         let message_copy = self.props.tx.message.clone();
 
+        // ENS names replace addresses, which are still shown on hover
         let from = self.props.tx.from.clone();
         let from_copy = from.clone();
+        let from_label = self.props.tx.from_name.clone().unwrap_or_else(|| from.clone());
 
         let to = self.props.tx.to.clone();
         let to_copy = to.clone();
+        let to_label = match (self.props.tx.kind.as_deref(), &self.props.tx.to_name) {
+            (Some("contract_creation"), _) => "New contract".to_string(),
+            (_, Some(name)) => name.clone(),
+            _ => to.clone(),
+        };
+
+        // Messages that were not plain UTF-8 are tagged with their original encoding
+        let encoding = if self.props.tx.encoding != "utf8" {
+            html! {
+                <>
+                    { crate::view_helpers::space() }
+                    <span class="tag is-light is-small tx-encoding" title="Original encoding of the message">{ &self.props.tx.encoding }</span>
+                </>
+            }
+        } else {
+            html! {}
+        };
+
+        // Clicking the kind shows only transactions of the same kind
+        let kind = match (self.props.tx.kind.clone(), self.props.tx.kind_label()) {
+            (Some(kind), Some(label)) => html! {
+                <>
+                    { crate::view_helpers::space() }
+                    <a
+                        class="tag is-info is-light is-small tx-kind"
+                        title="Filter for this kind of transaction"
+                        onclick={self.props.add_filter.reform(move |_| TransactionFilter{field: TransactionFilterField::Kind, operation: TransactionFilterOperation::Include, text: kind.clone()})}>
+                        { label }
+                    </a>
+                </>
+            },
+            _ => html! {},
+        };
+
+        // Clicking the language shows only messages in the same language
+        let lang = match self.props.tx.lang.clone() {
+            Some(lang) => {
+                let name = language_name(&lang).to_string();
+                html! {
+                    <>
+                        { crate::view_helpers::space() }
+                        <a
+                            class="tag is-light is-small tx-lang"
+                            title="Filter for this language"
+                            onclick={self.props.add_filter.reform(move |_| TransactionFilter{field: TransactionFilterField::Lang, operation: TransactionFilterOperation::Include, text: lang.clone()})}>
+                            { name }
+                        </a>
+                    </>
+                }
+            }
+            None => html! {},
+        };
+
+        // Copies of the message grouped into this card
+        let repeats = match self.props.tx.repeats {
+            Some(repeats) if repeats > 0 => html! {
+                <>
+                    { crate::view_helpers::space() }
+                    <span class="tag is-warning is-light is-small tx-repeats" title={format!("{} more transactions with this message", repeats)}>{ format!("×{}", repeats + 1) }</span>
+                </>
+            },
+            _ => html! {},
+        };
+
+        let status = if self.props.tx.status == "failure" {
+            html! {
+                <>
+                    { crate::view_helpers::space() }
+                    <span class="tag is-danger is-light is-small tx-status" title="The transaction was reverted">{ "Failed" }</span>
+                </>
+            }
+        } else {
+            html! {}
+        };
+
+        // Data URIs are tagged with their media type and payload size
+        let media_type = match (&self.props.tx.media_type, self.props.tx.payload_size) {
+            (Some(media_type), Some(size)) => html! {
+                <>
+                    { crate::view_helpers::space() }
+                    <span class="tag is-light is-small tx-media-type">{ format!("{} ({} bytes)", media_type, size) }</span>
+                </>
+            },
+            _ => html! {},
+        };
 
         html! {
-            <div class=classes!("card", animate) key=self.props.tx.hash.clone()>
+            <div class=classes!("card", animate) key=self.props.tx.key()>
                 <div class="card-header card-header-tx">
                     <p class="card-header-title">
                         <span>{ "Tx" }</span>
@@ -74,6 +183,12 @@ impl Component for TransactionCard {
                         <span class="has-text-weight-normal tx-hash">{ &self.props.tx.hash }</span>
                         { crate::view_helpers::space() }
                         <span class="has-text-weight-normal is-size-7 tx-timestamp" title=iso_time>{ format!("({})", human_time) }</span>
+                        { kind }
+                        { lang }
+                        { repeats }
+                        { status }
+                        { encoding }
+                        { media_type }
                     </p>
                     <div class="card-header-filters">
                         <button
@@ -89,7 +204,7 @@ impl Component for TransactionCard {
                                 <i class="fas fa-search-minus" aria-hidden="true"></i>
                         </button>
                     </div>
-                    <button class="card-header-icon" title="View on Etherscan">
+                    <button class="card-header-icon" title="View on block explorer">
                         <a href=link target="_blank" class="icon">
                             <i class="fas fa-external-link" aria-hidden="true"></i>
                         </a>
@@ -101,7 +216,7 @@ impl Component for TransactionCard {
                         <p class="card-header-title">
                             <span>{ "From" }</span>
                             { crate::view_helpers::space() }
-                            <span class="has-text-weight-normal tx-hash">{ from.clone() }</span>
+                            <span class="has-text-weight-normal tx-hash" title=from.clone()>{ from_label }</span>
                         </p>
                         <div class="card-header-filters pr-6">
                             <button
@@ -123,7 +238,7 @@ impl Component for TransactionCard {
                         <p class="card-header-title">
                             <span>{ "To" }</span>
                             { crate::view_helpers::space() }
-                            <span class="has-text-weight-normal tx-hash">{ to.clone() }</span>
+                            <span class="has-text-weight-normal tx-hash" title=to.clone()>{ to_label }</span>
                         </p>
                         <div class="card-header-filters">
                             <button
@@ -142,18 +257,101 @@ impl Component for TransactionCard {
                     </div>
                 </div>
 
+                { self.view_details() }
+
                 <div>
-                    <figure class="highlight">
-                        <pre>
-                            <code>
-                                <TransactionMessage
-                                    message={self.props.tx.message.clone()}
-                                    filter={self.props.text_filter.clone()} />
-                            </code>
-                        </pre>
-                    </figure>
+                    { self.view_message() }
                 </div>
             </div>
         }
     }
 }
+
+impl TransactionCard {
+    // Block, value and gas of the transaction, for the fields the backend knows about
+    fn view_details(&self) -> Html {
+        let tx = &self.props.tx;
+
+        let mut details: Vec<(&str, String)> = vec![];
+        if let Some(block_number) = tx.block_number {
+            details.push(("Block", block_number.to_string()));
+        }
+        if let Some(value) = &tx.value {
+            details.push(("Value", format!("{} ETH", crate::string::format_units(value, 18))));
+        }
+        match (tx.gas_used, &tx.gas_price) {
+            (Some(gas_used), Some(gas_price)) => details.push((
+                "Gas",
+                format!("{} at {} gwei", gas_used, crate::string::format_units(gas_price, 9)),
+            )),
+            (Some(gas_used), None) => details.push(("Gas", gas_used.to_string())),
+            (None, Some(gas_price)) => details.push((
+                "Gas price",
+                format!("{} gwei", crate::string::format_units(gas_price, 9)),
+            )),
+            (None, None) => {}
+        }
+        if let Some(nonce) = tx.nonce {
+            details.push(("Nonce", nonce.to_string()));
+        }
+
+        html! {
+            <div class="card-header card-header-details is-size-7">
+                {for details.into_iter().map(|(label, value)| html! {
+                    <span>
+                        <span class="has-text-weight-semibold">{ label }</span>
+                        { crate::view_helpers::space() }
+                        <span>{ value }</span>
+                    </span>
+                })}
+            </div>
+        }
+    }
+
+    // Images and JSON sent as data: URIs are shown for what they are, anything else as text
+    // Flagged messages are blurred until clicked
+    fn view_message(&self) -> Html {
+        if self.props.tx.moderation.as_deref() == Some("flagged") && !self.revealed {
+            return html! {
+                <div class="tx-flagged" title="Show message" onclick={self.link.callback(|_| Msg::Reveal)}>
+                    <div class="tx-flagged-content">{ self.view_message_content() }</div>
+                    <span class="tag is-warning tx-flagged-label">{ "Flagged message, click to show" }</span>
+                </div>
+            };
+        }
+
+        self.view_message_content()
+    }
+
+    fn view_message_content(&self) -> Html {
+        let tx = &self.props.tx;
+        let media_type = tx.media_type.as_deref().unwrap_or_default();
+
+        if data_uri::is_previewable_image(media_type) {
+            return html! {
+                <figure class="image tx-preview">
+                    <img src=tx.message.clone() alt=media_type.to_string() referrerpolicy="no-referrer" loading="lazy" />
+                </figure>
+            };
+        }
+
+        let text = if data_uri::is_json(media_type) {
+            data_uri::payload_text(&tx.message).and_then(|text| data_uri::pretty_json(&text))
+        } else {
+            None
+        };
+
+        html! {
+            <figure class="highlight">
+                <pre>
+                    <code>
+                        <TransactionMessage
+                            message={text.clone().unwrap_or_else(|| tx.message.clone())}
+                            filter={self.props.text_filter.clone()}
+                            links={if text.is_some() { vec![] } else { tx.links.clone() }} />
+                    </code>
+                </pre>
+            </figure>
+        }
+    }
+}
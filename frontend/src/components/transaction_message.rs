@@ -1,3 +1,4 @@
+use crate::model::Link;
 use crate::string::StringPartType;
 use std::sync::Arc;
 use yew::prelude::*;
@@ -6,6 +7,9 @@ use yew::prelude::*;
 pub struct Props {
     pub message: String,
     pub filter: Arc<Option<String>>,
+    // Links found in the message by the backend, with byte offsets in it
+    #[prop_or_default]
+    pub links: Vec<Link>,
 }
 
 pub struct TransactionMessage {
@@ -34,8 +38,31 @@ impl Component for TransactionMessage {
     }
 
     fn view(&self) -> Html {
+        let message = &self.props.message;
+        let mut parts: Vec<Html> = vec![];
+        let mut last = 0;
+
+        for link in self.props.links.iter() {
+            // Offsets that don't fit the message are ignored rather than trusted
+            let text = match message.get(link.start..link.end) {
+                Some(text) if link.start >= last => text,
+                _ => continue,
+            };
+
+            parts.push(self.view_text(&message[last..link.start]));
+            parts.push(self.view_link(link, text));
+            last = link.end;
+        }
+        parts.push(self.view_text(&message[last..]));
+
+        html! { <>{ for parts.into_iter() }</> }
+    }
+}
+
+impl TransactionMessage {
+    fn view_text(&self, text: &str) -> Html {
         if let Some(f) = (*self.props.filter).clone() {
-            let parts = crate::string::split_keep(&self.props.message, &f);
+            let parts = crate::string::split_keep(text, &f);
 
             html! {
                 {for parts.iter().map(|part| {
@@ -46,7 +73,26 @@ impl Component for TransactionMessage {
                 })}
             }
         } else {
-            html! { <span>{ &self.props.message } </span> }
+            html! { <span>{ text } </span> }
+        }
+    }
+
+    // Risky links are left as text, with a warning badge
+    fn view_link(&self, link: &Link, text: &str) -> Html {
+        match link.risk_label() {
+            Some(label) => html! {
+                <>
+                    <span class="tx-link-risky" title=format!("{}: {}", label, link.domain)>{ self.view_text(text) }</span>
+                    <span class="tag is-danger is-light is-small tx-link-warning" title=label>
+                        <i class="fas fa-exclamation-triangle" aria-hidden="true"></i>
+                        { crate::view_helpers::space() }
+                        { label }
+                    </span>
+                </>
+            },
+            None => html! {
+                <a href=link.url.clone() target="_blank" rel="noopener noreferrer nofollow">{ self.view_text(text) }</a>
+            },
         }
     }
 }
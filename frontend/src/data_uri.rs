@@ -0,0 +1,44 @@
+use wasm_bindgen::JsValue;
+
+// Image types that get a preview. SVG is only ever loaded through an <img> element,
+// where browsers neither run its scripts nor fetch the resources it links to
+const PREVIEW_IMAGE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "image/bmp",
+    "image/svg+xml",
+];
+
+pub fn is_previewable_image(media_type: &str) -> bool {
+    PREVIEW_IMAGE_TYPES.contains(&media_type)
+}
+
+pub fn is_json(media_type: &str) -> bool {
+    media_type == "application/json" || media_type.ends_with("+json")
+}
+
+// Decoded payload of a data: URI, read as UTF-8
+pub fn payload_text(uri: &str) -> Option<String> {
+    let (header, payload) = uri.split_once(',')?;
+
+    if header.split(';').any(|p| p.trim().eq_ignore_ascii_case("base64")) {
+        let payload: String = payload.split_whitespace().collect();
+        // atob returns one char per decoded byte
+        let decoded = yew::utils::window().atob(&payload).ok()?;
+        let bytes: Vec<u8> = decoded.chars().map(|c| c as u8).collect();
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    } else {
+        js_sys::decode_uri_component(payload).ok().map(String::from)
+    }
+}
+
+pub fn pretty_json(text: &str) -> Option<String> {
+    let value = js_sys::JSON::parse(text).ok()?;
+    let pretty =
+        js_sys::JSON::stringify_with_replacer_and_space(&value, &JsValue::NULL, &JsValue::from_f64(2.0)).ok()?;
+
+    Some(String::from(pretty))
+}
@@ -1,7 +1,7 @@
-use crate::components::filter::{Filter, TransactionFilter, TransactionFilterOperation};
+use crate::components::filter::{Filter, TransactionFilter, TransactionFilterField, TransactionFilterOperation};
 use crate::components::hero::Hero;
 use crate::components::transaction_card::TransactionCard;
-use crate::model::{Model, Msg, Transaction};
+use crate::model::{language_name, Chain, Model, Msg, Transaction};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -13,6 +13,7 @@ use yew::virtual_dom::{VList, VNode};
 use yew::web_sys::Element;
 
 pub mod components;
+pub mod data_uri;
 pub mod model;
 pub mod string;
 pub mod view_helpers;
@@ -21,19 +22,43 @@ const SECONDS_IN_DAY: u64 = 86400;
 
 const BACKEND_URL: &str = "";
 const FETCH_INTERVAL: u64 = 5;
+const DEFAULT_EXPLORER_URL: &str = "https://etherscan.io";
+const MAX_LANGUAGE_FACETS: usize = 8;
 
 const MOBILE_WIDTH: i32 = 768;
 const NODE_PADDING: i32 = 2;
-const ELEM_HEIGHT_DESKTOP: i32 = 150;
-const ELEM_HEIGHT_MOBILE: i32 = 220;
+const ELEM_HEIGHT_DESKTOP: i32 = 174;
+const ELEM_HEIGHT_MOBILE: i32 = 260;
 const ELEM_MARGIN: i32 = 24;
 
 #[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 struct QueryParams {
+    chain: Option<String>,
     text_filter: Option<String>,
     filters: Option<Vec<TransactionFilter>>,
 }
 
+// Keeps the newest transaction of every group of repeated messages, counting the others
+fn group_duplicates(transactions: Vec<Transaction>) -> Vec<Transaction> {
+    let mut grouped: Vec<Transaction> = vec![];
+    let mut groups: HashMap<String, usize> = HashMap::new();
+
+    for tx in transactions {
+        match groups.get(tx.group()) {
+            Some(index) => {
+                let repeats = grouped[*index].repeats.unwrap_or(0);
+                grouped[*index].repeats = Some(repeats + 1);
+            }
+            None => {
+                groups.insert(tx.group().to_string(), grouped.len());
+                grouped.push(tx);
+            }
+        }
+    }
+
+    grouped
+}
+
 fn current_timestamp() -> u64 {
     let current_date: js_sys::Date = js_sys::Date::new_0();
     let current_timestamp: f64 = current_date.get_time() / (1000_f64);
@@ -52,11 +77,15 @@ impl Component for Model {
             error: None,
             text_filter: Arc::new(None),
             feed_paused: false,
+            group_duplicates: false,
+            chains: vec![],
+            chain: None,
             transaction_filters: vec![],
             inclusion_filters: HashMap::new(),
             exclusion_filters: HashMap::new(),
             link,
             fetch_task: None,
+            chains_task: None,
             debounce_task: None,
             poll_task: None,
             animation_task: None,
@@ -140,6 +169,29 @@ impl Component for Model {
 
                 true
             }
+            Msg::ChainsFetched(chains) => {
+                self.chains = chains;
+
+                true
+            }
+            Msg::SelectChain(chain) => {
+                if self.chain.as_ref() == Some(&chain) {
+                    return false;
+                }
+
+                // Start over with the transactions of the new chain
+                self.chain = Some(chain);
+                self.transactions.clear();
+                self.first_fetch_done = false;
+                self.fetch_task = None;
+                self.poll_task = None;
+                self.animation_task = None;
+
+                self.save_filters_to_url();
+                self.link.send_message(Msg::FetchTransactions);
+
+                true
+            }
             Msg::DebounceFilter(filter) => {
                 self.debounce_task = None;
 
@@ -204,6 +256,11 @@ impl Component for Model {
 
                 true
             }
+            Msg::ToggleGroupDuplicates => {
+                self.group_duplicates = !self.group_duplicates;
+
+                true
+            }
             Msg::ToggleFeedPaused => {
                 self.feed_paused = !self.feed_paused;
 
@@ -238,6 +295,7 @@ impl Component for Model {
         );
         let spacer_style = format!("transform: translateY({}px)", self.offset_y());
 
+        let explorer_url = self.explorer_url();
         let transactions = self.filtered_transactions();
         let min = self.start_index() as usize;
         let max = (self.start_index() + self.visible_items_count() - 1) as usize;
@@ -269,8 +327,11 @@ impl Component for Model {
                         </div>
                     </div>
 
+                    { self.view_language_facets() }
+
                     <div class="settings">
                         <span class="transactions-description">
+                            { self.view_chain_selector() }
                             { format!{"{} transactions in the last 24 hours", transactions.len()} }
                         </span>
                         <label class="checkbox">
@@ -278,6 +339,11 @@ impl Component for Model {
                             { view_helpers::space() }
                             { "Pause feed" }
                         </label>
+                        <label class="checkbox">
+                            <input type="checkbox" onchange={self.link.callback(|_| Msg::ToggleGroupDuplicates)} checked={self.group_duplicates} />
+                            { view_helpers::space() }
+                            { "Group repeated messages" }
+                        </label>
                     </div>
 
                     <hr />
@@ -288,6 +354,7 @@ impl Component for Model {
                                 {for transactions.iter().enumerate().filter(|&(i, _)| i >= min && i <= max).map(|(_, tx)| html! {
                                     <TransactionCard
                                         tx={tx.clone()}
+                                        explorer_url={explorer_url.clone()}
                                         now={now}
                                         text_filter={self.text_filter.clone()}
                                         add_filter={self.link.callback(Msg::AddFilter)} />
@@ -321,6 +388,8 @@ impl Component for Model {
             let params: QueryParams = qs_config.deserialize_str(&query_string).unwrap_or_default();
 
             // In case the are params in query string, init app state
+            self.chain = params.chain;
+
             let mut messages: Vec<Msg> = vec![];
             if let Some(text_filter) = params.text_filter {
                 messages.push(Msg::EditFilter(text_filter));
@@ -335,6 +404,8 @@ impl Component for Model {
                 initial_state.emit(());
             }
 
+            self.chains_task = Some(self.fetch_chains());
+
             // Fetch first batch of transactions
             let initial_fetch = self.link.callback(|_| Msg::FetchTransactions);
             initial_fetch.emit(());
@@ -357,9 +428,17 @@ impl Model {
                 }
             });
 
-        let uri = match after {
-            None => format!("{}{}", BACKEND_URL, "/transactions"),
-            Some(a) => format!("{}{}?after={}", BACKEND_URL, "/transactions", a),
+        let mut params = vec![];
+        if let Some(chain) = &self.chain {
+            params.push(format!("chain={}", chain));
+        }
+        if let Some(a) = after {
+            params.push(format!("after={}", a));
+        }
+
+        let uri = match params.is_empty() {
+            true => format!("{}{}", BACKEND_URL, "/v2/transactions"),
+            false => format!("{}{}?{}", BACKEND_URL, "/v2/transactions", params.join("&")),
         };
 
         let request = Request::get(uri).body(Nothing).expect("Failed to build request");
@@ -367,10 +446,84 @@ impl Model {
         FetchService::fetch(request, callback).expect("Failed to start request")
     }
 
+    // Chains
+    fn fetch_chains(&self) -> FetchTask {
+        let callback = self
+            .link
+            .callback(move |response: Response<Json<anyhow::Result<Vec<Chain>>>>| {
+                let (meta, Json(body)) = response.into_parts();
+
+                match (meta.status.is_success(), body) {
+                    (true, Ok(data)) => Msg::ChainsFetched(data),
+                    (false, Ok(_)) => Msg::HttpError(format!("Generic error, received {}", meta.status)),
+                    (_, Err(error)) => Msg::HttpError(format!("{:?}", error)),
+                }
+            });
+
+        let uri = format!("{}{}", BACKEND_URL, "/chains");
+        let request = Request::get(uri).body(Nothing).expect("Failed to build request");
+
+        FetchService::fetch(request, callback).expect("Failed to start request")
+    }
+
+    // Explorer of the chain being shown, the first chain being the backend default
+    fn explorer_url(&self) -> String {
+        let chain = match &self.chain {
+            Some(name) => self.chains.iter().find(|chain| &chain.name == name),
+            None => self.chains.first(),
+        };
+
+        chain
+            .map(|chain| chain.explorer_url.clone())
+            .unwrap_or_else(|| DEFAULT_EXPLORER_URL.to_string())
+    }
+
+    // Most common languages of the last 24 hours, clicking one only shows messages in that language
+    fn view_language_facets(&self) -> Html {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for lang in self.transactions.iter().filter_map(|tx| tx.lang.as_deref()) {
+            *counts.entry(lang).or_insert(0) += 1;
+        }
+
+        if counts.len() < 2 {
+            return html! {};
+        }
+
+        let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts.truncate(MAX_LANGUAGE_FACETS);
+
+        html! {
+            <div class="field is-grouped is-grouped-multiline language-facets">
+                {for counts.into_iter().map(|(lang, count)| {
+                    let filter = TransactionFilter {
+                        field: TransactionFilterField::Lang,
+                        operation: TransactionFilterOperation::Include,
+                        text: lang.to_string(),
+                    };
+
+                    html! {
+                        <div class="control">
+                            <a class="tags has-addons" title="Filter for this language" onclick={self.link.callback(move |_| Msg::AddFilter(filter.clone()))}>
+                                <span class="tag is-light">{ language_name(lang) }</span>
+                                <span class="tag">{ count }</span>
+                            </a>
+                        </div>
+                    }
+                })}
+            </div>
+        }
+    }
+
     fn in_inclusion_filters(&self, tx: &&Transaction) -> bool {
         self.inclusion_filters.values().all(|v| {
-            v.iter()
-                .any(|r| r.text == tx.from || r.text == tx.to || r.text == tx.message)
+            v.iter().any(|r| {
+                r.text == tx.from
+                    || r.text == tx.to
+                    || r.text == tx.message
+                    || Some(&r.text) == tx.kind.as_ref()
+                    || Some(&r.text) == tx.lang.as_ref()
+            })
         })
     }
 
@@ -395,7 +548,17 @@ impl Model {
         keep = if self.exclusion_filters.keys().len() > 0 {
             !(self.in_exclusion_filters(tx.message.clone())
                 || self.in_exclusion_filters(tx.from.clone())
-                || self.in_exclusion_filters(tx.to.clone()))
+                || self.in_exclusion_filters(tx.to.clone())
+                || tx
+                    .kind
+                    .clone()
+                    .map(|kind| self.in_exclusion_filters(kind))
+                    .unwrap_or(false)
+                || tx
+                    .lang
+                    .clone()
+                    .map(|lang| self.in_exclusion_filters(lang))
+                    .unwrap_or(false))
         } else {
             true
         };
@@ -404,7 +567,7 @@ impl Model {
     }
 
     fn filtered_transactions(&self) -> Vec<Transaction> {
-        if let Some(ref f) = *self.text_filter {
+        let transactions: Vec<Transaction> = if let Some(ref f) = *self.text_filter {
             self.transactions
                 .iter()
                 .filter(|tx| tx.message.to_lowercase().contains(&f.to_lowercase()))
@@ -417,6 +580,12 @@ impl Model {
                 .filter(|tx| self.filter_transaction(tx))
                 .cloned()
                 .collect()
+        };
+
+        if self.group_duplicates {
+            group_duplicates(transactions)
+        } else {
+            transactions
         }
     }
 
@@ -433,6 +602,33 @@ impl Model {
         }
     }
 
+    fn view_chain_selector(&self) -> Html {
+        if self.chains.len() < 2 {
+            return VNode::from(VList::new());
+        }
+
+        let selected = self
+            .chain
+            .clone()
+            .or_else(|| self.chains.first().map(|chain| chain.name.clone()));
+        let onchange = self.link.batch_callback(|event: ChangeData| match event {
+            ChangeData::Select(select) => vec![Msg::SelectChain(select.value())],
+            _ => vec![],
+        });
+
+        html! {
+            <div class="select is-small chain-selector">
+                <select onchange=onchange>
+                    {for self.chains.iter().map(|chain| html! {
+                        <option value=chain.name.clone() selected={selected.as_ref() == Some(&chain.name)}>
+                            { &chain.name }
+                        </option>
+                    })}
+                </select>
+            </div>
+        }
+    }
+
     fn view_error(&self) -> Html {
         if self.error.is_some() {
             html! {
@@ -478,7 +674,11 @@ impl Model {
             Some(self.transaction_filters.clone())
         };
 
-        let params = QueryParams { text_filter, filters };
+        let params = QueryParams {
+            chain: self.chain.clone(),
+            text_filter,
+            filters,
+        };
         let encoded = serde_qs::to_string(&params).unwrap_or_default();
 
         let window = yew::utils::window();
@@ -13,6 +13,9 @@ pub enum Msg {
     TransactionsFetched(Vec<Transaction>),
     RemoveAnimation(usize),
     HttpError(String),
+    // Chains
+    ChainsFetched(Vec<Chain>),
+    SelectChain(String),
     // Filter
     DebounceFilter(String),
     EditFilter(String),
@@ -21,6 +24,7 @@ pub enum Msg {
     RemoveFilter(TransactionFilter),
     // Toggle
     ToggleFeedPaused,
+    ToggleGroupDuplicates,
     // Virtual scroll
     OnScroll,
 }
@@ -33,6 +37,12 @@ pub struct Model {
     pub error: Option<String>,
     pub text_filter: Arc<Option<String>>,
     pub feed_paused: bool,
+    // Whether repeated messages are shown once, with the number of copies
+    pub group_duplicates: bool,
+    // Chains served by the backend, and the one being shown. The backend picks
+    // its default chain until the list is fetched
+    pub chains: Vec<Chain>,
+    pub chain: Option<String>,
     // Advanced filters
     pub transaction_filters: Vec<TransactionFilter>,
     pub inclusion_filters: HashMap<String, Vec<TransactionFilter>>,
@@ -40,6 +50,7 @@ pub struct Model {
     // Cmd bus
     pub link: ComponentLink<Self>,
     pub fetch_task: Option<FetchTask>,
+    pub chains_task: Option<FetchTask>,
     pub debounce_task: Option<TimeoutTask>,
     pub poll_task: Option<TimeoutTask>,
     pub animation_task: Option<TimeoutTask>,
@@ -52,26 +63,159 @@ pub struct Model {
     pub row_height: i32,
 }
 
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Chain {
+    pub id: u64,
+    pub name: String,
+    pub explorer_url: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Link {
+    pub start: usize,
+    pub end: usize,
+    pub url: String,
+    pub domain: String,
+    #[serde(default)]
+    pub risk: Option<String>,
+}
+
+impl Link {
+    pub fn risk_label(&self) -> Option<&'static str> {
+        match self.risk.as_deref() {
+            Some("denylisted") => Some("Known drainer site"),
+            Some("punycode") => Some("Lookalike domain name"),
+            Some("fresh_tld") => Some("Domain often used by scams"),
+            Some(_) => Some("Risky link"),
+            None => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Properties)]
 pub struct Transaction {
     // Backend fields
-    #[serde(rename = "h")]
     pub hash: String,
-    #[serde(rename = "m")]
     pub message: String,
-    #[serde(rename = "t")]
     pub timestamp: u64,
-    #[serde(deserialize_with = "default_address")]
+    #[serde(default = "no_address", deserialize_with = "default_address")]
     pub from: String,
-    #[serde(deserialize_with = "default_address")]
+    #[serde(default = "no_address", deserialize_with = "default_address")]
     pub to: String,
+    #[serde(default)]
+    pub from_name: Option<String>,
+    #[serde(default)]
+    pub to_name: Option<String>,
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub block_number: Option<u64>,
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default = "default_status")]
+    pub status: String,
+    #[serde(default)]
+    pub gas_used: Option<u64>,
+    #[serde(default)]
+    pub gas_price: Option<String>,
+    #[serde(default)]
+    pub nonce: Option<u64>,
+    #[serde(default = "default_encoding")]
+    pub encoding: String,
+    #[serde(default)]
+    pub log_index: Option<u64>,
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub payload_size: Option<u64>,
+    #[serde(default)]
+    pub lang: Option<String>,
+    #[serde(default)]
+    pub duplicate_of: Option<String>,
+    #[serde(default)]
+    pub spam_score: Option<f32>,
+    #[serde(default)]
+    pub moderation: Option<String>,
+    #[serde(default)]
+    pub links: Vec<Link>,
     // Local model
     pub animate: Option<bool>,
+    // Number of copies grouped into this transaction
+    pub repeats: Option<usize>,
+}
+
+impl Transaction {
+    pub fn kind_label(&self) -> Option<&'static str> {
+        match self.kind.as_deref() {
+            Some("contract_creation") => Some("contract creation"),
+            Some("self_transfer") => Some("self-transfer"),
+            Some("transfer") => Some("transfer"),
+            Some("contract_call") => Some("contract call"),
+            _ => None,
+        }
+    }
+
+    // Transactions with the same message, or a nearly identical one, share a group
+    pub fn group(&self) -> &str {
+        self.duplicate_of.as_deref().unwrap_or(&self.hash)
+    }
+
+    // A transaction shows up once per event log carrying a message
+    pub fn key(&self) -> String {
+        match self.log_index {
+            Some(log_index) => format!("{}-{}", self.hash, log_index),
+            None => self.hash.clone(),
+        }
+    }
+}
+
+// English names of the ISO 639-3 codes the backend detects most often
+const LANGUAGE_NAMES: &[(&str, &str)] = &[
+    ("eng", "English"),
+    ("cmn", "Chinese"),
+    ("rus", "Russian"),
+    ("spa", "Spanish"),
+    ("por", "Portuguese"),
+    ("fra", "French"),
+    ("deu", "German"),
+    ("ita", "Italian"),
+    ("jpn", "Japanese"),
+    ("kor", "Korean"),
+    ("ukr", "Ukrainian"),
+    ("tur", "Turkish"),
+    ("vie", "Vietnamese"),
+    ("arb", "Arabic"),
+    ("pes", "Persian"),
+    ("hin", "Hindi"),
+    ("ind", "Indonesian"),
+    ("pol", "Polish"),
+    ("nld", "Dutch"),
+    ("heb", "Hebrew"),
+];
+
+pub fn language_name(code: &str) -> &str {
+    LANGUAGE_NAMES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+        .unwrap_or(code)
+}
+
+fn no_address() -> String {
+    "-".to_string()
+}
+
+fn default_status() -> String {
+    "unknown".to_string()
+}
+
+fn default_encoding() -> String {
+    "utf8".to_string()
 }
 
 fn default_address<'de, D>(d: D) -> Result<String, D::Error>
 where
     D: Deserializer<'de>,
 {
-    Deserialize::deserialize(d).map(|x: Option<_>| x.unwrap_or_else(|| "-".to_string()))
+    Deserialize::deserialize(d).map(|x: Option<String>| x.filter(|x| !x.is_empty()).unwrap_or_else(no_address))
 }
@@ -36,3 +36,21 @@ pub fn split_keep<'a>(text: &'a str, r: &'a str) -> Vec<StringPart> {
 
     result
 }
+
+// Formats an integer amount of the smallest unit, e.g. wei, in a unit with `decimals` decimals,
+// keeping at most 6 of them. Amounts that can't be parsed are returned as they are
+pub fn format_units(amount: &str, decimals: u32) -> String {
+    let amount: u128 = match amount.parse() {
+        Ok(amount) => amount,
+        Err(_) => return amount.to_string(),
+    };
+
+    let unit = 10u128.pow(decimals);
+    let fraction = format!("{:0width$}", amount % unit, width = decimals as usize);
+    let fraction = fraction[..std::cmp::min(6, fraction.len())].trim_end_matches('0');
+
+    match fraction {
+        "" => format!("{}", amount / unit),
+        fraction => format!("{}.{}", amount / unit, fraction),
+    }
+}
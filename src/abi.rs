@@ -0,0 +1,360 @@
+use once_cell::sync::Lazy;
+use web3::ethabi::param_type::Reader;
+use web3::ethabi::{self, ParamType, Token};
+use web3::signing::keccak256;
+
+const SELECTOR_SIZE: usize = 4;
+const WORD_SIZE: usize = 32;
+
+// Functions commonly called on mainnet. Their input data is never shown as is,
+// only the text found in their string and bytes arguments
+const KNOWN_FUNCTIONS: &[&str] = &[
+    // ERC-20
+    "transfer(address,uint256)",
+    "approve(address,uint256)",
+    "transferFrom(address,address,uint256)",
+    "increaseAllowance(address,uint256)",
+    // ERC-721 and ERC-1155
+    "setApprovalForAll(address,bool)",
+    "safeTransferFrom(address,address,uint256)",
+    "safeTransferFrom(address,address,uint256,bytes)",
+    "safeTransferFrom(address,address,uint256,uint256,bytes)",
+    "mint(uint256)",
+    // ERC-677
+    "transferAndCall(address,uint256,bytes)",
+    // WETH
+    "deposit()",
+    "withdraw(uint256)",
+    // ENS
+    "setName(string)",
+    "setText(bytes32,string,string)",
+    "commit(bytes32)",
+    "register(string,address,uint256,bytes32)",
+    "renew(string,uint256)",
+    // Misc
+    "claim()",
+    "multicall(bytes[])",
+    "execute(bytes,bytes[],uint256)",
+];
+
+pub struct Function {
+    pub signature: &'static str,
+    pub selector: [u8; 4],
+    pub params: Vec<ParamType>,
+}
+
+impl Function {
+    fn parse(signature: &'static str) -> Self {
+        let start = signature.find('(').expect("Function signature without arguments");
+        let types = &signature[start + 1..signature.len() - 1];
+        let params = if types.is_empty() {
+            vec![]
+        } else {
+            types
+                .split(',')
+                .map(|t| Reader::read(t).expect("Invalid type in function signature"))
+                .collect()
+        };
+
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&keccak256(signature.as_bytes())[..SELECTOR_SIZE]);
+
+        Self {
+            signature,
+            selector,
+            params,
+        }
+    }
+}
+
+static FUNCTIONS: Lazy<Vec<Function>> = Lazy::new(|| KNOWN_FUNCTIONS.iter().map(|s| Function::parse(s)).collect());
+
+pub fn known_function(input: &[u8]) -> Option<&'static Function> {
+    if input.len() < SELECTOR_SIZE {
+        return None;
+    }
+
+    FUNCTIONS.iter().find(|f| f.selector == input[..SELECTOR_SIZE])
+}
+
+// Whether the input is laid out as a contract call: a 4-byte selector followed by 32-byte words.
+// Calls to unknown functions are recognized by the layout of their arguments
+pub fn is_call(input: &[u8]) -> bool {
+    if input.len() < SELECTOR_SIZE || (input.len() - SELECTOR_SIZE) % WORD_SIZE != 0 {
+        return false;
+    }
+
+    if known_function(input).is_some() {
+        return true;
+    }
+
+    let data = &input[SELECTOR_SIZE..];
+    !data.is_empty() && plausible_arguments(data)
+}
+
+// Addresses and integers are left-padded, with zeros or with ones for negative integers.
+// Text padded with NUL bytes is right-padded
+fn is_left_padded(word: &[u8]) -> bool {
+    word[..12].iter().all(|b| *b == 0) || word[..12].iter().all(|b| *b == 0xff)
+}
+
+// Whether every word could be an argument: a left-padded value, or part of the
+// length-prefixed area that an offset in the head points to
+fn plausible_arguments(data: &[u8]) -> bool {
+    let words: Vec<&[u8]> = data.chunks(WORD_SIZE).collect();
+    let mut in_tail = vec![false; words.len()];
+
+    for (index, word) in words.iter().enumerate() {
+        let offset = match word_to_usize(word) {
+            Some(offset) if offset > index * WORD_SIZE && offset % WORD_SIZE == 0 && offset < data.len() => offset,
+            _ => continue,
+        };
+
+        let length = match word_to_usize(words[offset / WORD_SIZE]) {
+            Some(length) if offset + WORD_SIZE + length <= data.len() => length,
+            _ => continue,
+        };
+
+        let start = offset / WORD_SIZE + 1;
+        let end = start + length.div_ceil(WORD_SIZE);
+        for covered in in_tail[start..end].iter_mut() {
+            *covered = true;
+        }
+    }
+
+    words
+        .iter()
+        .zip(in_tail)
+        .all(|(word, in_tail)| in_tail || is_left_padded(word))
+}
+
+fn word_to_usize(word: &[u8]) -> Option<usize> {
+    // Anything that doesn't fit comfortably in a usize can't be an offset or a length
+    if word[..WORD_SIZE - 4].iter().any(|b| *b != 0) {
+        return None;
+    }
+
+    let mut value = 0usize;
+    for b in &word[WORD_SIZE - 4..] {
+        value = (value << 8) | *b as usize;
+    }
+
+    Some(value)
+}
+
+// Dynamic arguments of a call to an unknown function. Every head word that points to a
+// valid length-prefixed area inside the data is assumed to be a string or bytes argument
+fn dynamic_arguments(data: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut arguments = vec![];
+
+    for (index, word) in data.chunks(WORD_SIZE).enumerate() {
+        let offset = match word_to_usize(word) {
+            Some(offset) if offset > index * WORD_SIZE && offset % WORD_SIZE == 0 => offset,
+            _ => continue,
+        };
+
+        if offset + WORD_SIZE > data.len() {
+            continue;
+        }
+
+        let length = match word_to_usize(&data[offset..offset + WORD_SIZE]) {
+            Some(length) if length > 0 && offset + WORD_SIZE + length <= data.len() => length,
+            _ => continue,
+        };
+
+        let start = offset + WORD_SIZE;
+        arguments.push((index, data[start..start + length].to_vec()));
+    }
+
+    arguments
+}
+
+// String and bytes arguments of a call, labelled with the function and the position
+// of the argument they come from, e.g. `setName(string)[0]`
+pub fn text_arguments(input: &[u8]) -> Vec<(String, Vec<u8>)> {
+    if input.len() < SELECTOR_SIZE {
+        return vec![];
+    }
+
+    let data = &input[SELECTOR_SIZE..];
+
+    match known_function(input) {
+        Some(function) => match ethabi::decode(&function.params, data) {
+            Ok(tokens) => tokens
+                .into_iter()
+                .enumerate()
+                .filter_map(|(index, token)| match token {
+                    Token::String(s) => Some((index, s.into_bytes())),
+                    Token::Bytes(b) => Some((index, b)),
+                    _ => None,
+                })
+                .map(|(index, bytes)| (format!("{}[{}]", function.signature, index), bytes))
+                .collect(),
+            Err(_) => vec![],
+        },
+        None => {
+            let selector = hex::encode(&input[..SELECTOR_SIZE]);
+
+            dynamic_arguments(data)
+                .into_iter()
+                .map(|(index, bytes)| (format!("0x{}[{}]", selector, index), bytes))
+                .collect()
+        }
+    }
+}
+
+// String and bytes values in the non-indexed data of an event log, labelled with the
+// first bytes of the event signature hash and the position of the value in the data
+pub fn log_text_arguments(topics: &[[u8; 32]], data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    if data.is_empty() || data.len() % WORD_SIZE != 0 {
+        return vec![];
+    }
+
+    // Anonymous events have no signature topic
+    let event = topics
+        .first()
+        .map(|topic| format!("0x{}", hex::encode(&topic[..SELECTOR_SIZE])))
+        .unwrap_or_else(|| "anonymous".to_string());
+
+    dynamic_arguments(data)
+        .into_iter()
+        .map(|(index, bytes)| (format!("{}[{}]", event, index), bytes))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use web3::ethabi::ethereum_types::{Address, U256};
+
+    fn call(signature: &'static str, tokens: &[Token]) -> Vec<u8> {
+        let function = Function::parse(signature);
+        [&function.selector[..], &ethabi::encode(tokens)].concat()
+    }
+
+    #[test]
+    fn test_selectors() {
+        assert_eq!(
+            Function::parse("transfer(address,uint256)").selector,
+            [0xa9, 0x05, 0x9c, 0xbb]
+        );
+        assert_eq!(
+            Function::parse("approve(address,uint256)").selector,
+            [0x09, 0x5e, 0xa7, 0xb3]
+        );
+        assert_eq!(Function::parse("deposit()").selector, [0xd0, 0xe3, 0x0d, 0xb0]);
+    }
+
+    #[test]
+    fn test_known_call_without_text() {
+        let input = call(
+            "transfer(address,uint256)",
+            &[
+                Token::Address(Address::repeat_byte(0x41)),
+                Token::Uint(U256::from(0x4141)),
+            ],
+        );
+
+        assert!(is_call(&input));
+        assert!(text_arguments(&input).is_empty());
+    }
+
+    #[test]
+    fn test_known_call_with_text() {
+        let input = call(
+            "setText(bytes32,string,string)",
+            &[
+                Token::FixedBytes(vec![1; 32]),
+                Token::String("url".to_string()),
+                Token::String("https://example.com".to_string()),
+            ],
+        );
+
+        assert!(is_call(&input));
+        assert_eq!(
+            text_arguments(&input),
+            vec![
+                ("setText(bytes32,string,string)[1]".to_string(), b"url".to_vec()),
+                (
+                    "setText(bytes32,string,string)[2]".to_string(),
+                    b"https://example.com".to_vec()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_call_with_text() {
+        let input = call(
+            "post(uint256,string)",
+            &[
+                Token::Uint(U256::from(7)),
+                Token::String("hello from a contract".to_string()),
+            ],
+        );
+
+        assert!(is_call(&input));
+        assert_eq!(
+            text_arguments(&input),
+            vec![(
+                format!("0x{}[1]", hex::encode(&input[..4])),
+                b"hello from a contract".to_vec()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_text_is_not_a_call() {
+        // Same length as a call with a single argument
+        let input = b"Some text whose length is exactly thirty-six";
+        assert!(!is_call(&input[..SELECTOR_SIZE + WORD_SIZE]));
+        assert!(!is_call(b"gm"));
+    }
+
+    #[test]
+    fn test_nul_padded_text_is_not_a_call() {
+        let mut input = b"hello!".to_vec();
+        input.resize(SELECTOR_SIZE + WORD_SIZE, 0);
+        assert!(!is_call(&input));
+
+        let mut input = b"gm".to_vec();
+        input.resize(SELECTOR_SIZE + 3 * WORD_SIZE, 0);
+        input[SELECTOR_SIZE + 2 * WORD_SIZE..SELECTOR_SIZE + 2 * WORD_SIZE + 4].copy_from_slice(b"fren");
+        assert!(!is_call(&input));
+    }
+
+    #[test]
+    fn test_unknown_call_without_text() {
+        let input = call(
+            "stake(address,uint256,uint256)",
+            &[
+                Token::Address(Address::repeat_byte(0x41)),
+                Token::Uint(U256::from(0x4141)),
+                Token::Uint(U256::zero()),
+            ],
+        );
+
+        assert!(is_call(&input));
+    }
+
+    #[test]
+    fn test_log_text_arguments() {
+        let topic = keccak256(b"Memo(address,uint256,string)");
+        let data = ethabi::encode(&[
+            Token::Uint(U256::from(1)),
+            Token::String("thanks for lunch".to_string()),
+        ]);
+
+        assert_eq!(
+            log_text_arguments(&[topic], &data),
+            vec![(
+                format!("0x{}[1]", hex::encode(&topic[..4])),
+                b"thanks for lunch".to_vec()
+            )]
+        );
+
+        let data = ethabi::encode(&[Token::Uint(U256::from(1)), Token::Uint(U256::from(2))]);
+        assert!(log_text_arguments(&[topic], &data).is_empty());
+        assert!(log_text_arguments(&[topic], &[]).is_empty());
+    }
+}
@@ -0,0 +1,34 @@
+use crate::transaction;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// A transaction whose input data could be decoded to text
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[schema(as = v1::Transaction)]
+pub struct Transaction {
+    /// Transaction hash
+    #[serde(rename = "h")]
+    pub hash: String,
+    /// Text decoded from the transaction input data
+    #[serde(rename = "m")]
+    pub message: String,
+    /// Unix timestamp (seconds) of the block that included the transaction
+    #[serde(rename = "t")]
+    pub timestamp: u64,
+    /// Sender address
+    pub from: Option<String>,
+    /// Recipient address, missing for contract deployments
+    pub to: Option<String>,
+}
+
+impl From<&transaction::Transaction> for Transaction {
+    fn from(tx: &transaction::Transaction) -> Self {
+        Self {
+            hash: tx.hash.clone(),
+            message: tx.message.clone(),
+            timestamp: tx.timestamp,
+            from: tx.from.clone(),
+            to: tx.to.clone(),
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use crate::filter;
+use crate::transaction::{self, Encoding, Kind, LinkRisk, Moderation, Source};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Outcome of a transaction
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[serde(rename_all = "lowercase")]
+#[schema(as = v2::TransactionStatus)]
+pub enum TransactionStatus {
+    Success,
+    Failure,
+    /// The receipt of the transaction was not available when it was captured
+    Unknown,
+}
+
+/// What a transaction does, as far as its sender and recipient tell
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[serde(rename_all = "snake_case")]
+#[schema(as = v2::TransactionKind)]
+pub enum TransactionKind {
+    /// Deployment of a new contract, the transaction has no recipient
+    ContractCreation,
+    /// Sender and recipient are the same address
+    SelfTransfer,
+    /// Transfer to an externally owned account
+    Transfer,
+    /// Call to a contract
+    ContractCall,
+}
+
+impl From<Kind> for TransactionKind {
+    fn from(kind: Kind) -> Self {
+        match kind {
+            Kind::ContractCreation => TransactionKind::ContractCreation,
+            Kind::SelfTransfer => TransactionKind::SelfTransfer,
+            Kind::Transfer => TransactionKind::Transfer,
+            Kind::ContractCall => TransactionKind::ContractCall,
+        }
+    }
+}
+
+impl From<TransactionKind> for Kind {
+    fn from(kind: TransactionKind) -> Self {
+        match kind {
+            TransactionKind::ContractCreation => Kind::ContractCreation,
+            TransactionKind::SelfTransfer => Kind::SelfTransfer,
+            TransactionKind::Transfer => Kind::Transfer,
+            TransactionKind::ContractCall => Kind::ContractCall,
+        }
+    }
+}
+
+/// Criteria that transactions must all satisfy
+#[derive(Deserialize, Default, Clone, Debug, PartialEq, ToSchema)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::InputObject))]
+#[schema(as = v2::TransactionFilter)]
+pub struct TransactionFilter {
+    /// Sender or recipient address
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Sender address
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Recipient address
+    #[serde(default)]
+    pub to: Option<String>,
+    /// Minimum timestamp, inclusive
+    #[serde(default)]
+    pub since: Option<u64>,
+    /// Maximum timestamp, inclusive
+    #[serde(default)]
+    pub until: Option<u64>,
+    /// Case insensitive text that the message must contain
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Kind of transaction
+    #[serde(default)]
+    pub kind: Option<TransactionKind>,
+    /// ISO 639-3 code of the language of the message
+    #[serde(default)]
+    pub lang: Option<String>,
+}
+
+impl From<TransactionFilter> for filter::TransactionFilter {
+    fn from(filter: TransactionFilter) -> Self {
+        Self {
+            address: filter.address,
+            from: filter.from,
+            to: filter.to,
+            since: filter.since,
+            until: filter.until,
+            text: filter.text,
+            kind: filter.kind.map(Into::into),
+            lang: filter.lang,
+        }
+    }
+}
+
+/// Where the message of a transaction was found
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[serde(rename_all = "lowercase")]
+#[schema(as = v2::MessageSource)]
+pub enum MessageSource {
+    /// Transaction input data
+    Input,
+    /// Non-indexed data of an event log emitted by the transaction
+    Log,
+}
+
+/// How the message was encoded in the transaction data before being decoded to text
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[serde(rename_all = "lowercase")]
+#[schema(as = v2::MessageEncoding)]
+pub enum MessageEncoding {
+    Utf8,
+    /// UTF-16, either with a byte order mark or mostly made of ASCII characters
+    Utf16,
+    /// Hex digits, optionally prefixed with 0x, spelling UTF-8 text
+    Hex,
+    /// Padded base64, standard or URL-safe, spelling UTF-8 text
+    Base64,
+}
+
+/// Outcome of the moderation of a message
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[serde(rename_all = "lowercase")]
+#[schema(as = v2::MessageModeration)]
+pub enum MessageModeration {
+    /// The message may be offensive or a scam, clients should not show it without a warning
+    Flagged,
+    /// The message is left out of the API and of webhooks, so this is never returned
+    Hidden,
+}
+
+impl From<Moderation> for MessageModeration {
+    fn from(moderation: Moderation) -> Self {
+        match moderation {
+            Moderation::Flagged => MessageModeration::Flagged,
+            Moderation::Hidden => MessageModeration::Hidden,
+        }
+    }
+}
+
+/// Why a link looks like phishing
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::Enum))]
+#[serde(rename_all = "snake_case")]
+#[schema(as = v2::MessageLinkRisk)]
+pub enum MessageLinkRisk {
+    /// The domain, or one of its parents, is a known drainer site
+    Denylisted,
+    /// Internationalized domain name, possibly a lookalike of a well known one
+    Punycode,
+    /// The TLD is favoured by drainer sites
+    FreshTld,
+}
+
+impl From<LinkRisk> for MessageLinkRisk {
+    fn from(risk: LinkRisk) -> Self {
+        match risk {
+            LinkRisk::Denylisted => MessageLinkRisk::Denylisted,
+            LinkRisk::Punycode => MessageLinkRisk::Punycode,
+            LinkRisk::FreshTld => MessageLinkRisk::FreshTld,
+        }
+    }
+}
+
+/// A URL or a domain name found in a message
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[schema(as = v2::MessageLink)]
+pub struct MessageLink {
+    /// Byte offset of the link in the UTF-8 encoded message
+    pub start: u64,
+    /// Byte offset of the end of the link, exclusive
+    pub end: u64,
+    /// Link as written in the message, with `https://` added to bare domain names
+    pub url: String,
+    pub domain: String,
+    /// Set when the link looks like phishing, clients should not make it clickable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub risk: Option<MessageLinkRisk>,
+}
+
+impl MessageLink {
+    fn new(message: &str, link: &transaction::Link) -> Option<Self> {
+        let text = message.get(link.start..link.end)?;
+        let url = if text.contains("://") {
+            text.to_string()
+        } else {
+            format!("https://{}", text)
+        };
+
+        Some(Self {
+            start: link.start as u64,
+            end: link.end as u64,
+            url,
+            domain: link.domain.clone(),
+            risk: link.risk.map(Into::into),
+        })
+    }
+}
+
+/// A transaction whose input data, or one of whose event logs, could be decoded to text
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[cfg_attr(feature = "graphql", derive(async_graphql::SimpleObject))]
+#[schema(as = v2::Transaction)]
+pub struct Transaction {
+    /// Transaction hash
+    pub hash: String,
+    /// Text decoded from the transaction input data or from one of its event logs
+    pub message: String,
+    /// Unix timestamp (seconds) of the block that included the transaction
+    pub timestamp: u64,
+    /// Number of the block that included the transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    /// Position of the transaction inside its block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_index: Option<u64>,
+    /// Sender address, missing when it is unknown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// ENS name of the sender, when it has one and the server resolves names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_name: Option<String>,
+    /// Recipient address, missing for contract deployments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// ENS name of the recipient, when it has one and the server resolves names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_name: Option<String>,
+    /// Missing for transactions captured before kinds were recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<TransactionKind>,
+    /// Amount of wei transferred, as a decimal string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    pub status: TransactionStatus,
+    /// Gas used by the transaction, from its receipt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_used: Option<u64>,
+    /// Price paid per unit of gas in wei, as a decimal string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<String>,
+    /// Number of transactions sent by the sender before this one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    /// How likely the message is to be actual text, from 0 to 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// Function argument the message was decoded from, e.g. `setName(string)[0]`, when the
+    /// transaction is a contract call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub argument: Option<String>,
+    pub encoding: MessageEncoding,
+    /// MIME type of the payload, e.g. `image/png`, when the message is a `data:` URI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    /// Size in bytes of the decoded payload, when the message is a `data:` URI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload_size: Option<u64>,
+    /// ISO 639-3 code of the language of the message, e.g. `eng` or `cmn`, when it could be told
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// Hash of an earlier transaction with the same message, or a nearly identical one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+    /// Likelihood that the message is spam, from 0 to 1, based on repeated messages and on
+    /// the number of messages recently sent by the same address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spam_score: Option<f32>,
+    /// Number of other transactions with the same message collapsed into this one, only set
+    /// when asking for `collapse=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicates: Option<u64>,
+    /// Set when the message was flagged or hidden by moderation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<MessageModeration>,
+    /// URLs and domain names found in the message
+    pub links: Vec<MessageLink>,
+    pub source: MessageSource,
+    /// Position of the log in the block, when the message comes from an event log
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<u64>,
+}
+
+impl From<&transaction::Transaction> for Transaction {
+    fn from(tx: &transaction::Transaction) -> Self {
+        let status = match tx.status {
+            Some(true) => TransactionStatus::Success,
+            Some(false) => TransactionStatus::Failure,
+            None => TransactionStatus::Unknown,
+        };
+
+        Self {
+            hash: tx.hash.clone(),
+            message: tx.message.clone(),
+            timestamp: tx.timestamp,
+            block_number: tx.block_number,
+            transaction_index: tx.transaction_index,
+            from: tx.from.clone(),
+            from_name: None,
+            to: tx.to.clone(),
+            to_name: None,
+            kind: tx.kind.map(Into::into),
+            value: tx.value.clone(),
+            status,
+            gas_used: tx.gas_used,
+            gas_price: tx.gas_price.clone(),
+            nonce: tx.nonce,
+            confidence: tx.confidence,
+            argument: tx.argument.clone(),
+            encoding: match tx.encoding {
+                Encoding::Utf8 => MessageEncoding::Utf8,
+                Encoding::Utf16 => MessageEncoding::Utf16,
+                Encoding::Hex => MessageEncoding::Hex,
+                Encoding::Base64 => MessageEncoding::Base64,
+            },
+            media_type: tx.media_type.clone(),
+            payload_size: tx.payload_size,
+            lang: tx.lang.clone(),
+            duplicate_of: tx.duplicate_of.clone(),
+            spam_score: tx.spam_score,
+            duplicates: None,
+            moderation: tx.moderation.map(Into::into),
+            links: tx
+                .links
+                .iter()
+                .filter_map(|link| MessageLink::new(&tx.message, link))
+                .collect(),
+            source: match tx.source {
+                Source::Input => MessageSource::Input,
+                Source::Log => MessageSource::Log,
+            },
+            log_index: tx.log_index,
+        }
+    }
+}
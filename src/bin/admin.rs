@@ -0,0 +1,151 @@
+use anyhow::Result;
+use dotenv::dotenv;
+use interprether::cache::{parse_member, save_transactions};
+use interprether::chain::Chain;
+use interprether::codec::{self, Format};
+use interprether::moderation::{self, Override};
+use interprether::redis;
+use interprether::schema;
+
+// Records rewritten per call to Redis
+const MIGRATION_BATCH_SIZE: usize = 500;
+
+const USAGE: &str = "Usage:
+    admin moderate <tx hash> <visible|flagged|hidden>    Override the moderation of a transaction
+    admin unmoderate <tx hash>                           Go back to the moderation lists
+    admin overrides                                      List the overrides
+    admin migrate-index                                  Split the members of blocks into one member per transaction
+    admin migrate [<version>]                            Upgrade stored transactions to a record version, the latest by default
+    admin migrations                                     List the record versions
+    admin cleaner-status                                 Show the runs of the cleaner
+
+Commands apply to the chain set by CHAIN";
+
+// Members used to hold a JSON array with the transactions of a block. Each transaction
+// is stored on its own, then the block member is removed
+async fn migrate_index(chain: &Chain, format: &Format) -> Result<()> {
+    let members = redis::legacy_members(chain).await?;
+    log::info!("Migrating {} block members of {}", members.len(), chain.name);
+
+    let mut count = 0;
+    for (member, _) in members {
+        let transactions = parse_member(member.as_bytes())?;
+        save_transactions(chain, format, &transactions).await?;
+        redis::zrem(chain, member).await?;
+        count += transactions.len();
+    }
+
+    // Readers may have seen transactions both in their block and on their own
+    redis::incr_generation(chain).await?;
+    log::info!("Stored {} transactions of {} on their own", count, chain.name);
+
+    Ok(())
+}
+
+// Rewrites the stored transactions older than `target`, one version at a time, in the
+// configured format. Newer ones are left as they are, and transactions removed or written
+// again since they were read are not rewritten
+async fn migrate(chain: &Chain, format: &Format, target: u64) -> Result<()> {
+    if target > schema::VERSION {
+        return Err(anyhow::anyhow!(
+            "Version {} is newer than the latest one, {}",
+            target,
+            schema::VERSION
+        ));
+    }
+
+    migrate_index(chain, format).await?;
+
+    let mut upgraded: Vec<(String, Vec<u8>, Vec<u8>)> = vec![];
+    let mut skipped = 0;
+    for (id, member) in redis::get_transaction_records(chain).await? {
+        let (version, record) = schema::upgrade(&member, target)?;
+        if version >= target {
+            skipped += 1;
+            continue;
+        }
+
+        upgraded.push((id, member, codec::encode(&record, format)?));
+    }
+
+    let count = upgraded.len();
+    let mut replaced = 0;
+    while !upgraded.is_empty() {
+        let batch = upgraded.split_off(upgraded.len().saturating_sub(MIGRATION_BATCH_SIZE));
+        replaced += redis::replace_transactions(chain, batch).await?;
+    }
+
+    log::info!(
+        "Upgraded {} transactions of {} to version {}, {} were up to date or newer, {} changed since they were read",
+        replaced,
+        chain.name,
+        target,
+        skipped,
+        count as u64 - replaced
+    );
+
+    Ok(())
+}
+
+async fn run(chain: &Chain, format: &Format, args: &[String]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["moderate", hash, decision] => {
+            let hash = moderation::parse_hash(hash)?;
+            let decision: Override = decision.parse()?;
+            redis::set_moderation_override(chain, &hash, decision.as_str()).await?;
+            log::info!("{} is now {} on {}", hash, decision.as_str(), chain.name);
+        }
+        ["unmoderate", hash] => {
+            let hash = moderation::parse_hash(hash)?;
+            if redis::delete_moderation_override(chain, &hash).await? {
+                log::info!("Removed the override of {} on {}", hash, chain.name);
+            } else {
+                log::info!("{} has no override on {}", hash, chain.name);
+            }
+        }
+        ["overrides"] => {
+            for (hash, decision) in redis::get_moderation_overrides(chain).await? {
+                println!("{} {}", hash, decision);
+            }
+        }
+        ["migrate-index"] => migrate_index(chain, format).await?,
+        ["migrate"] => migrate(chain, format, schema::VERSION).await?,
+        ["migrate", version] => migrate(chain, format, version.parse()?).await?,
+        ["migrations"] => {
+            for migration in schema::MIGRATIONS {
+                println!(
+                    "{} -> {}: {}",
+                    migration.from,
+                    migration.from + 1,
+                    migration.description
+                );
+            }
+        }
+        ["cleaner-status"] => {
+            for (field, value) in redis::get_cleaner_status(chain).await? {
+                println!("{} {}", field, value);
+            }
+        }
+        _ => return Err(anyhow::anyhow!("{}", USAGE)),
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+
+    std::env::set_var("RUST_LOG", "info");
+    env_logger::init();
+
+    redis::connect().await?;
+
+    let chain = Chain::from_env()?;
+    let format = Format::from_env()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    run(&chain, &format, &args).await
+}
@@ -1,9 +1,55 @@
 use anyhow::Result;
 use dotenv::dotenv;
+use interprether::chain::Chain;
+use interprether::cleaner::Cleaner;
 use interprether::redis;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-const SECONDS_IN_DAY: u64 = 86400;
+// Seconds between two runs of the daemon
+const DEFAULT_INTERVAL: u64 = 3600;
+
+const USAGE: &str = "Usage:
+    cleaner [--dry-run]                          Remove the transactions older than CLEANER_RETENTION seconds, or only report them
+    cleaner --daemon [--interval <seconds>]      Keep cleaning every interval, CLEANER_INTERVAL or an hour by default";
+
+struct Options {
+    daemon: bool,
+    interval: u64,
+}
+
+fn parse_args(cleaner: &mut Cleaner, args: &[String]) -> Result<Options> {
+    let mut options = Options {
+        daemon: false,
+        interval: match std::env::var("CLEANER_INTERVAL") {
+            Ok(interval) => interval
+                .parse()
+                .map_err(|_| anyhow::anyhow!("CLEANER_INTERVAL must be a number of seconds"))?,
+            Err(_) => DEFAULT_INTERVAL,
+        },
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => cleaner.dry_run = true,
+            "--daemon" => options.daemon = true,
+            "--interval" => match args.next().map(|interval| interval.parse()) {
+                Some(Ok(interval)) if interval > 0 => options.interval = interval,
+                _ => return Err(anyhow::anyhow!("{}", USAGE)),
+            },
+            _ => return Err(anyhow::anyhow!("{}", USAGE)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -12,13 +58,35 @@ async fn main() -> Result<()> {
     std::env::set_var("RUST_LOG", "info");
     env_logger::init();
 
-    let start = SystemTime::now();
-    let since_the_epoch = start.duration_since(UNIX_EPOCH).expect("Time went backwards");
+    redis::connect().await?;
+
+    let mut cleaner = Cleaner::from_env()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = parse_args(&mut cleaner, &args)?;
+    let chains = Chain::list_from_env()?;
+
+    // Tells the instances apart in the lock, so that one never releases the lock of another
+    let token = format!("{:016x}", rand::random::<u64>());
+
+    if !options.daemon {
+        for chain in chains.iter() {
+            cleaner.clean_exclusively(chain, now(), &token).await?;
+        }
+
+        return Ok(());
+    }
 
-    let max = since_the_epoch.as_secs() - SECONDS_IN_DAY;
+    log::info!("Cleaner started, running every {} seconds", options.interval);
 
-    let cleaned_values = redis::zremrange_by_score(max).await?;
-    log::info!("Removed {} values from set", cleaned_values);
+    let mut interval = tokio::time::interval(Duration::from_secs(options.interval));
+    loop {
+        interval.tick().await;
 
-    Ok(())
+        // A failed run is retried at the next tick
+        for chain in chains.iter() {
+            if let Err(error) = cleaner.clean_exclusively(chain, now(), &token).await {
+                log::error!("Error while cleaning {}: {:?}", chain.name, error);
+            }
+        }
+    }
 }
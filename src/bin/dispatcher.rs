@@ -0,0 +1,126 @@
+use anyhow::Result;
+use dotenv::dotenv;
+use interprether::cache::parse_members;
+use interprether::chain::Chain;
+use interprether::cursor::Cursor;
+use interprether::moderation;
+use interprether::redis;
+use interprether::transaction::Moderation;
+use interprether::webhook::{self, Delivery, Subscription};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+async fn subscriptions() -> Result<HashMap<String, Subscription>> {
+    let mut subscriptions = HashMap::new();
+    for item in redis::get_subscriptions().await? {
+        let subscription: Subscription = serde_json::from_str(&item)?;
+        subscriptions.insert(subscription.id.clone(), subscription);
+    }
+
+    Ok(subscriptions)
+}
+
+fn spawn_dispatch(client: &webhook::Client, subscription: &Subscription, delivery: Delivery) {
+    let client = client.clone();
+    let subscription = subscription.clone();
+
+    tokio::spawn(async move {
+        if let Err(error) = webhook::dispatch(&client, &subscription, delivery).await {
+            log::error!("Error while dispatching webhook: {:?}", error);
+        }
+    });
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+
+    std::env::set_var("RUST_LOG", "info");
+    env_logger::init();
+
+    redis::connect().await?;
+
+    log::info!("Webhook dispatcher started");
+
+    let client = webhook::Client::from_env()?;
+
+    // Only deliver transactions captured from now on when starting for the first time
+    let mut cursors = vec![];
+    for chain in Chain::list_from_env()? {
+        let cursor = match redis::get_webhook_cursor(&chain).await? {
+            Some(cursor) => Cursor::parse(&cursor)?,
+            None => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .expect("Time went backwards");
+                Cursor::new(now.as_secs())
+            }
+        };
+
+        log::info!("Dispatching {} transactions from {}", chain.name, cursor.last_seen);
+        cursors.push((chain, cursor));
+    }
+
+    loop {
+        let subscriptions = subscriptions().await?;
+
+        // Decisions of admins take precedence over the moderation stored with the transactions
+        let mut overrides = HashMap::new();
+        for (chain, _) in cursors.iter() {
+            overrides.insert(chain.name.clone(), moderation::load_overrides(chain).await?);
+        }
+
+        // Retries whose backoff is over
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards");
+        for item in redis::take_due_deliveries(now.as_secs()).await? {
+            let mut delivery: Delivery = serde_json::from_str(&item)?;
+            if let Some(decision) = overrides
+                .get(&delivery.chain)
+                .and_then(|overrides| overrides.get(&delivery.transaction.hash))
+            {
+                decision.apply(&mut delivery.transaction);
+            }
+
+            // Transactions hidden by an admin since the first attempt are not retried
+            if delivery.transaction.moderation == Some(Moderation::Hidden) {
+                continue;
+            }
+
+            // Subscriptions deleted in the meantime are not retried anymore
+            if let Some(subscription) = subscriptions.get(&delivery.subscription_id) {
+                spawn_dispatch(&client, subscription, delivery);
+            }
+        }
+
+        // Transactions captured since the last iteration
+        for (chain, cursor) in cursors.iter_mut() {
+            // Read again from the last timestamp seen, as more transactions can be stored with it
+            let members = redis::zrange_by_score_with_scores(chain, cursor.last_seen).await?;
+            let raw: Vec<Vec<u8>> = members.into_iter().rev().map(|(member, _)| member).collect();
+            let mut transactions = cursor.advance(parse_members(&raw));
+            if transactions.is_empty() {
+                continue;
+            }
+
+            if let Some(overrides) = overrides.get(&chain.name) {
+                for tx in transactions.iter_mut() {
+                    if let Some(decision) = overrides.get(&tx.hash) {
+                        decision.apply(tx);
+                    }
+                }
+            }
+
+            for subscription in subscriptions.values() {
+                for tx in transactions.iter().filter(|tx| subscription.wants(chain, tx)) {
+                    spawn_dispatch(&client, subscription, Delivery::new(subscription, chain, tx));
+                }
+            }
+
+            redis::set_webhook_cursor(chain, serde_json::to_string(cursor)?).await?;
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
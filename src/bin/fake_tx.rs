@@ -1,5 +1,8 @@
 use anyhow::Result;
 use dotenv::dotenv;
+use interprether::cache::save_transactions;
+use interprether::chain::Chain;
+use interprether::codec::Format;
 use interprether::{redis, transaction};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
@@ -20,6 +23,12 @@ async fn main() -> Result<()> {
     std::env::set_var("RUST_LOG", "info");
     env_logger::init();
 
+    redis::connect().await?;
+    redis::check_fake_data_namespace(std::env::args().any(|arg| arg == "--force"))?;
+
+    let chain = Chain::from_env()?;
+    let format = Format::from_env()?;
+
     let mut rng = rand::thread_rng();
 
     let now = SystemTime::now()
@@ -38,16 +47,27 @@ async fn main() -> Result<()> {
         None
     };
 
+    let kind = transaction::Kind::classify(&from, &to, || rng.gen_bool(0.5));
+
     let transactions = vec![transaction::Transaction {
         hash: format!("0x{}", rand_string(32)),
         message: rand_string(rng.gen_range(50..100)),
         timestamp: now.as_secs(),
         from,
         to,
+        kind: Some(kind),
+        block_number: Some(rng.gen_range(13_000_000..14_000_000)),
+        transaction_index: Some(rng.gen_range(0..200)),
+        value: Some(rng.gen::<u64>().to_string()),
+        status: Some(rng.gen_bool(0.9)),
+        gas_used: Some(rng.gen_range(21_000..100_000)),
+        gas_price: Some(rng.gen_range(1_000_000_000u64..200_000_000_000).to_string()),
+        nonce: Some(rng.gen_range(0..1000)),
+        confidence: Some(rng.gen_range(0.9..=1.0)),
+        ..Default::default()
     }];
 
-    let serialized_tx = serde_json::to_string(&transactions)?;
-    redis::zadd(now.as_secs(), serialized_tx).await?;
+    save_transactions(&chain, &format, &transactions).await?;
 
     log::info!("Inserted tx at {}", now.as_secs());
 
@@ -1,8 +1,56 @@
 use anyhow::Result;
 use dotenv::dotenv;
+use interprether::cache::{parse_members, save_transactions};
+use interprether::chain::Chain;
+use interprether::codec::Format;
+use interprether::extract::{extract_log_message, extract_message, ExtractorConfig};
+use interprether::links::LinkChecker;
+use interprether::moderation::Moderator;
+use interprether::spam::SpamDetector;
+use interprether::transaction::Kind;
 use interprether::{redis, transaction};
-use std::time::Duration;
-use web3::types::Bytes;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use web3::types::{Address, FilterBuilder, Transaction, TransactionReceipt, H256};
+
+// Addresses whose kind of account is remembered, the cache is emptied once full
+const MAX_KNOWN_ACCOUNTS: usize = 10_000;
+
+// Whether `address` holds code, remembering the answer since accounts hardly ever change kind
+async fn is_contract(
+    web3: &web3::Web3<web3::transports::Http>,
+    known_accounts: &mut HashMap<Address, bool>,
+    address: Address,
+) -> Result<bool> {
+    if let Some(is_contract) = known_accounts.get(&address) {
+        return Ok(*is_contract);
+    }
+
+    let code = web3.eth().code(address, None).await?;
+    if known_accounts.len() >= MAX_KNOWN_ACCOUNTS {
+        known_accounts.clear();
+    }
+    known_accounts.insert(address, !code.0.is_empty());
+
+    Ok(!code.0.is_empty())
+}
+
+async fn kind(
+    web3: &web3::Web3<web3::transports::Http>,
+    known_accounts: &mut HashMap<Address, bool>,
+    tx: &Transaction,
+) -> Result<Kind> {
+    let from = tx.from.map(|from| format!("{:?}", from));
+    let to = tx.to.map(|to| format!("{:?}", to));
+
+    // Only ask for the code of the recipient when it matters
+    let to_is_contract = match tx.to {
+        Some(to) if tx.from != Some(to) => is_contract(web3, known_accounts, to).await?,
+        _ => false,
+    };
+
+    Ok(Kind::classify(&from, &to, || to_is_contract))
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -11,13 +59,50 @@ async fn main() -> Result<()> {
     std::env::set_var("RUST_LOG", "info");
     env_logger::init();
 
+    redis::connect().await?;
+
     log::info!("Scanner started");
 
     let geth_url = std::env::var("WEB3_PROVIDER_URL").expect("WEB3_PROVIDER_URL must be set");
     let transport = web3::transports::Http::new(&geth_url)?;
     let web3 = web3::Web3::new(transport);
 
+    let chain = Chain::from_env()?;
+    let chain_id = web3.eth().chain_id().await?;
+    if chain_id.as_u64() != chain.id {
+        return Err(anyhow::anyhow!(
+            "{} has chain id {}, but the provider is on chain {}",
+            chain.name,
+            chain.id,
+            chain_id
+        ));
+    }
+    log::info!("Scanning {} (chain id {})", chain.name, chain.id);
+
+    let extractor_config = ExtractorConfig::from_env()?;
+    log::info!("Extracting messages with {:?}", extractor_config);
+
+    let moderator = Moderator::from_env()?;
+    if moderator.is_enabled() {
+        log::info!("Moderating messages");
+    }
+
+    let link_checker = LinkChecker::from_env()?;
+
+    let format = Format::from_env()?;
+    log::info!("Storing transactions as {:?}", format);
+
+    // Messages stored before a restart are remembered, so that repeats keep being spotted
+    let mut spam_detector = SpamDetector::from_env()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let members = redis::zrange_by_score_with_scores(&chain, now.saturating_sub(spam_detector.window())).await?;
+    let members: Vec<Vec<u8>> = members.into_iter().map(|(member, _)| member).collect();
+    for tx in parse_members(&members) {
+        spam_detector.remember(&tx);
+    }
+
     let mut latest_known_block_number = web3::types::U64::from(0);
+    let mut known_accounts: HashMap<Address, bool> = HashMap::new();
 
     loop {
         let current_block_number = web3.eth().block_number().await?;
@@ -35,76 +120,145 @@ async fn main() -> Result<()> {
                 .await?
                 .ok_or_else(|| anyhow::anyhow!("Block {} not found", block_number))?;
 
+            // Receipts are only needed for the few transactions carrying a message, and
+            // fetched once for those with messages both in their input and in their logs
+            let mut receipts: HashMap<H256, Option<TransactionReceipt>> = HashMap::new();
+
             let mut transactions: Vec<transaction::Transaction> = vec![];
             for tx in block.transactions.iter() {
-                let _ = extract_message(tx.input.clone()).map(|message| {
+                if let Ok(extracted) = extract_message(&tx.input.0, &extractor_config) {
+                    let receipt = web3.eth().transaction_receipt(tx.hash).await?;
+                    receipts.insert(tx.hash, receipt.clone());
+                    let kind = kind(&web3, &mut known_accounts, tx).await?;
+
                     transactions.push(transaction::Transaction {
-                        message,
+                        message: extracted.message,
                         hash: format!("{:?}", tx.hash),
                         timestamp: block.timestamp.as_u64(),
                         from: tx.from.map(|from| format!("{:?}", from)),
                         to: tx.to.map(|to| format!("{:?}", to)),
+                        kind: Some(kind),
+                        block_number: Some(block_number.as_u64()),
+                        transaction_index: tx.transaction_index.map(|index| index.as_u64()),
+                        value: Some(tx.value.to_string()),
+                        status: receipt
+                            .as_ref()
+                            .and_then(|r| r.status)
+                            .map(|status| status.as_u64() == 1),
+                        gas_used: receipt.and_then(|r| r.gas_used).map(|gas| gas.low_u64()),
+                        gas_price: Some(tx.gas_price.to_string()),
+                        nonce: Some(tx.nonce.low_u64()),
+                        confidence: Some(extracted.confidence),
+                        argument: extracted.argument,
+                        encoding: extracted.encoding,
+                        media_type: extracted.data_uri.as_ref().map(|d| d.media_type.clone()),
+                        payload_size: extracted.data_uri.as_ref().map(|d| d.size),
+                        lang: extracted.lang,
+                        // Filled in once the block is complete
+                        duplicate_of: None,
+                        spam_score: None,
+                        moderation: None,
+                        links: vec![],
+                        source: transaction::Source::Input,
+                        log_index: None,
                     });
-                });
+                }
             }
 
-            latest_known_block_number = block_number;
+            // Text emitted in event logs
+            let block_hash = block
+                .hash
+                .ok_or_else(|| anyhow::anyhow!("Block {} has no hash", block_number))?;
+            let logs = web3
+                .eth()
+                .logs(FilterBuilder::default().block_hash(block_hash).build())
+                .await?;
 
-            // Save info to redis
-            if !transactions.is_empty() {
-                log::info!("Saving {} txs with timestamp {}", transactions.len(), block.timestamp);
+            for log in logs.iter().filter(|log| !log.is_removed()) {
+                let topics: Vec<[u8; 32]> = log.topics.iter().map(|topic| topic.0).collect();
 
-                let serialized_tx = serde_json::to_string(&transactions)?;
-                redis::zadd(block.timestamp.as_u64(), serialized_tx).await?;
-            }
-        }
+                if let Ok(extracted) = extract_log_message(&topics, &log.data.0, &extractor_config) {
+                    let tx = block
+                        .transactions
+                        .iter()
+                        .find(|tx| Some(tx.hash) == log.transaction_hash);
 
-        tokio::time::sleep(Duration::from_secs(1)).await;
-    }
-}
+                    let receipt = match tx {
+                        Some(tx) => match receipts.get(&tx.hash) {
+                            Some(receipt) => receipt.clone(),
+                            None => {
+                                let receipt = web3.eth().transaction_receipt(tx.hash).await?;
+                                receipts.insert(tx.hash, receipt.clone());
+                                receipt
+                            }
+                        },
+                        None => None,
+                    };
 
-pub fn extract_message(input: Bytes) -> Result<String> {
-    let result = std::str::from_utf8(&input.0).map(|message| {
-        // Remove NULL bytes
-        message.replace(char::from(0), "").trim().to_string()
-    })?;
+                    let kind = match tx {
+                        Some(tx) => Some(kind(&web3, &mut known_accounts, tx).await?),
+                        None => None,
+                    };
 
-    if result.is_empty() {
-        Err(anyhow::anyhow!("Empty input data"))
-    } else {
-        Ok(result)
-    }
-}
+                    transactions.push(transaction::Transaction {
+                        message: extracted.message,
+                        hash: log
+                            .transaction_hash
+                            .map(|hash| format!("{:?}", hash))
+                            .unwrap_or_default(),
+                        timestamp: block.timestamp.as_u64(),
+                        from: tx.and_then(|tx| tx.from).map(|from| format!("{:?}", from)),
+                        to: tx.and_then(|tx| tx.to).map(|to| format!("{:?}", to)),
+                        kind,
+                        block_number: Some(block_number.as_u64()),
+                        transaction_index: log.transaction_index.map(|index| index.as_u64()),
+                        value: tx.map(|tx| tx.value.to_string()),
+                        // Reverted transactions don't emit logs
+                        status: Some(true),
+                        gas_used: receipt.and_then(|r| r.gas_used).map(|gas| gas.low_u64()),
+                        gas_price: tx.map(|tx| tx.gas_price.to_string()),
+                        nonce: tx.map(|tx| tx.nonce.low_u64()),
+                        confidence: Some(extracted.confidence),
+                        argument: extracted.argument,
+                        encoding: extracted.encoding,
+                        media_type: extracted.data_uri.as_ref().map(|d| d.media_type.clone()),
+                        payload_size: extracted.data_uri.as_ref().map(|d| d.size),
+                        lang: extracted.lang,
+                        // Filled in once the block is complete
+                        duplicate_of: None,
+                        spam_score: None,
+                        moderation: None,
+                        links: vec![],
+                        source: transaction::Source::Log,
+                        log_index: log.log_index.map(|index| index.as_u64()),
+                    });
+                }
+            }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+            for tx in transactions.iter_mut() {
+                let verdict = spam_detector.score(tx);
+                tx.duplicate_of = verdict.duplicate_of;
+                tx.spam_score = Some(verdict.spam_score);
+                tx.moderation = moderator.moderate(&tx.message);
+                // The payload of data URIs is no text to look for links in
+                if tx.media_type.is_none() {
+                    tx.links = link_checker.links(&tx.message);
+                }
+            }
 
-    #[test]
-    fn test_empty_message() {
-        let input: Vec<u8> = vec![];
-        let bytes: Bytes = input.into();
-        assert_eq!(extract_message(bytes).is_err(), true);
-    }
+            latest_known_block_number = block_number;
 
-    #[test]
-    fn test_null_message() {
-        let input: Vec<u8> = vec![0u8];
-        let bytes: Bytes = input.into();
-        assert_eq!(extract_message(bytes).is_err(), true);
-    }
+            // Save info to redis
+            if !transactions.is_empty() {
+                log::info!("Saving {} txs with timestamp {}", transactions.len(), block.timestamp);
 
-    #[test]
-    fn test_message_with_empty_chars() {
-        let input: Vec<u8> = vec![0u8, 32u8, 0u8];
-        let bytes: Bytes = input.into();
-        assert_eq!(extract_message(bytes).is_err(), true);
-    }
+                let replaced = save_transactions(&chain, &format, &transactions).await?;
+                if replaced > 0 {
+                    log::info!("Replaced {} txs of block {}", replaced, block_number);
+                }
+            }
+        }
 
-    #[test]
-    fn test_message_with_message() {
-        let input: Vec<u8> = vec![0u8, 32u8, 104u8, 101u8, 108u8, 108u8, 111u8, 33u8, 32u8, 0u8];
-        let bytes: Bytes = input.into();
-        assert_eq!(extract_message(bytes).unwrap(), "hello!");
+        tokio::time::sleep(Duration::from_secs(1)).await;
     }
 }
@@ -1,5 +1,8 @@
 use anyhow::Result;
 use dotenv::dotenv;
+use interprether::cache::save_transactions;
+use interprether::chain::Chain;
+use interprether::codec::Format;
 use interprether::{redis, transaction};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -13,6 +16,12 @@ async fn main() -> Result<()> {
     std::env::set_var("RUST_LOG", "info");
     env_logger::init();
 
+    redis::connect().await?;
+    redis::check_fake_data_namespace(std::env::args().any(|arg| arg == "--force"))?;
+
+    let chain = Chain::from_env()?;
+    let format = Format::from_env()?;
+
     let start = SystemTime::now();
     let since_the_epoch = start.duration_since(UNIX_EPOCH).expect("Time went backwards");
 
@@ -28,10 +37,19 @@ async fn main() -> Result<()> {
             timestamp: start,
             from: Some(format!("sender-0x{}", start)),
             to: Some(format!("sender-0x{}", start)),
+            kind: Some(transaction::Kind::SelfTransfer),
+            block_number: Some(start / STEP),
+            transaction_index: Some(0),
+            value: Some("0".to_string()),
+            status: Some(true),
+            gas_used: Some(21000),
+            gas_price: Some("1000000000".to_string()),
+            nonce: Some(counter),
+            confidence: Some(1.0),
+            ..Default::default()
         }];
 
-        let serialized_tx = serde_json::to_string(&transactions)?;
-        redis::zadd(start, serialized_tx).await?;
+        save_transactions(&chain, &format, &transactions).await?;
 
         start -= STEP;
         counter += 1;
@@ -0,0 +1,300 @@
+use crate::chain::Chain;
+use crate::codec::Format;
+use crate::moderation::{self, Override};
+use crate::redis;
+use crate::schema;
+use crate::transaction::Transaction;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Instant;
+use tokio::sync::{Mutex, RwLock};
+
+// Parses a stored transaction, upgrading records written by older versions or in another
+// format. Members written before the transaction index existed hold a JSON array of the
+// transactions of a block instead
+pub fn parse_member(member: &[u8]) -> Result<Vec<Transaction>> {
+    schema::decode(member)
+}
+
+// Parses the members that can be. The other ones, e.g. written by a newer version during a
+// rolling deploy, are logged and skipped rather than failing the whole read
+pub fn parse_members<S: AsRef<[u8]>>(members: &[S]) -> Vec<Transaction> {
+    let mut transactions: Vec<Transaction> = vec![];
+    for item in members.iter() {
+        match parse_member(item.as_ref()) {
+            Ok(parsed) => transactions.extend(parsed),
+            Err(error) => log::warn!("Skipping a member that could not be parsed: {:?}", error),
+        }
+    }
+
+    transactions
+}
+
+// Stores transactions, one member each. Storing a transaction again replaces it, returns
+// the number of transactions that were replaced with a different content
+pub async fn save_transactions(chain: &Chain, format: &Format, transactions: &[Transaction]) -> Result<u64> {
+    let entries = transactions
+        .iter()
+        .map(|tx| Ok((tx.id(), tx.timestamp, schema::encode(tx, format)?)))
+        .collect::<Result<Vec<(String, u64, Vec<u8>)>>>()?;
+
+    redis::add_transactions(chain, entries).await
+}
+
+#[derive(Default)]
+struct CacheState {
+    // Parsed transactions, grouped by score
+    entries: BTreeMap<u64, Vec<Transaction>>,
+    // Raw members stored with the highest score seen so far. The next incremental
+    // fetch starts from that score (inclusive), so these are used to skip members
+    // that were already parsed
+    latest_members: HashSet<Vec<u8>>,
+    max_score: Option<u64>,
+    // Moderation decisions of admins, applied to the transactions on their way out
+    overrides: HashMap<String, Override>,
+    generation: Option<u64>,
+    refreshed_at: Option<Instant>,
+}
+
+impl CacheState {
+    fn reset(&mut self, generation: u64) {
+        *self = CacheState {
+            generation: Some(generation),
+            ..Default::default()
+        };
+    }
+
+    fn insert(&mut self, members: Vec<(Vec<u8>, u64)>) -> Result<()> {
+        for (member, score) in members {
+            if Some(score) == self.max_score && self.latest_members.contains(&member) {
+                continue;
+            }
+
+            match parse_member(&member) {
+                Ok(parsed) => self.entries.entry(score).or_default().extend(parsed),
+                Err(error) => log::warn!("Skipping a member that could not be parsed: {:?}", error),
+            }
+
+            match self.max_score {
+                Some(max) if max > score => {}
+                Some(max) if max == score => {
+                    self.latest_members.insert(member);
+                }
+                _ => {
+                    self.max_score = Some(score);
+                    self.latest_members = HashSet::from([member]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn prune(&mut self, min: u64) {
+        self.entries = self.entries.split_off(&min);
+    }
+
+    fn window(&self, max: u64, min: u64) -> Vec<Transaction> {
+        if min > max {
+            return vec![];
+        }
+
+        self.entries
+            .range(min..=max)
+            .rev()
+            .flat_map(|(_, transactions)| transactions.iter().cloned())
+            .map(|mut tx| {
+                if let Some(decision) = self.overrides.get(&tx.hash) {
+                    decision.apply(&mut tx);
+                }
+                tx
+            })
+            .collect()
+    }
+}
+
+// In-process copy of the transactions window, shared by all the requests
+// served by the API. It is kept up to date incrementally, fetching only the
+// members added since the last refresh, and dropped as a whole when the
+// cleaner bumps the set generation. There is one cache per chain
+pub struct TransactionCache {
+    chain: Chain,
+    state: RwLock<CacheState>,
+    refresh_lock: Mutex<()>,
+}
+
+impl TransactionCache {
+    pub fn new(chain: Chain) -> Self {
+        Self {
+            chain,
+            state: RwLock::new(CacheState::default()),
+            refresh_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn chain(&self) -> &Chain {
+        &self.chain
+    }
+
+    // Brings the cache up to date with Redis, keeping only entries with score >= `min`.
+    // When several requests ask for a refresh at the same time only the first one hits
+    // Redis, the others wait for it and reuse its result
+    pub async fn refresh(&self, min: u64) -> Result<()> {
+        let requested_at = Instant::now();
+        let _guard = self.refresh_lock.lock().await;
+
+        let (generation, max_score) = {
+            let state = self.state.read().await;
+            if state.refreshed_at.map(|at| at >= requested_at).unwrap_or(false) {
+                return Ok(());
+            }
+
+            (state.generation, state.max_score)
+        };
+
+        let current_generation = redis::get_generation(&self.chain).await?;
+        let since = match max_score {
+            Some(max) if generation == Some(current_generation) => std::cmp::max(max, min),
+            _ => min,
+        };
+
+        let members = redis::zrange_by_score_with_scores(&self.chain, since).await?;
+
+        let overrides = moderation::load_overrides(&self.chain).await?;
+
+        let mut state = self.state.write().await;
+        if state.generation != Some(current_generation) {
+            log::info!(
+                "Transactions set generation of {} changed, invalidating cache",
+                self.chain.name
+            );
+            state.reset(current_generation);
+        }
+
+        state.insert(members)?;
+        state.prune(min);
+        state.overrides = overrides;
+        state.refreshed_at = Some(Instant::now());
+
+        Ok(())
+    }
+
+    // Loads raw members into the cache without going through Redis
+    pub async fn load(&self, members: Vec<(Vec<u8>, u64)>) -> Result<()> {
+        self.state.write().await.insert(members)
+    }
+
+    // Highest score of the cached transactions
+    pub async fn max_score(&self) -> Option<u64> {
+        self.state.read().await.max_score
+    }
+
+    // Returns the cached transactions with `min <= timestamp <= max`, newest first
+    pub async fn window(&self, max: u64, min: u64) -> Vec<Transaction> {
+        self.state.read().await.window(max, min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::Moderation;
+
+    fn member(hash: &str, timestamp: u64) -> (Vec<u8>, u64) {
+        let transaction = Transaction {
+            hash: hash.to_string(),
+            message: format!("Message {}", hash),
+            timestamp,
+            from: None,
+            to: None,
+            ..Default::default()
+        };
+
+        (schema::encode(&transaction, &Format::default()).unwrap(), timestamp)
+    }
+
+    fn hashes(transactions: Vec<Transaction>) -> Vec<String> {
+        transactions.into_iter().map(|tx| tx.hash).collect()
+    }
+
+    #[test]
+    fn test_window_is_newest_first() {
+        let mut state = CacheState::default();
+        state
+            .insert(vec![member("a", 10), member("b", 20), member("c", 30)])
+            .unwrap();
+
+        assert_eq!(hashes(state.window(30, 10)), vec!["c", "b", "a"]);
+        assert_eq!(hashes(state.window(25, 15)), vec!["b"]);
+        assert!(state.window(10, 30).is_empty());
+    }
+
+    #[test]
+    fn test_incremental_insert_skips_known_members() {
+        let mut state = CacheState::default();
+        state.insert(vec![member("a", 10), member("b", 20)]).unwrap();
+
+        // The next fetch starts from the max score, so "b" comes back again
+        state
+            .insert(vec![member("b", 20), member("c", 20), member("d", 30)])
+            .unwrap();
+
+        assert_eq!(hashes(state.window(30, 0)), vec!["d", "b", "c", "a"]);
+        assert_eq!(state.max_score, Some(30));
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut state = CacheState::default();
+        state
+            .insert(vec![member("a", 10), member("b", 20), member("c", 30)])
+            .unwrap();
+        state.prune(20);
+
+        assert_eq!(hashes(state.window(30, 0)), vec!["c", "b"]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut state = CacheState::default();
+        state.insert(vec![member("a", 10)]).unwrap();
+        state.reset(3);
+
+        assert!(state.window(30, 0).is_empty());
+        assert_eq!(state.max_score, None);
+        assert_eq!(state.generation, Some(3));
+    }
+
+    #[test]
+    fn test_insert_skips_unreadable_members() {
+        let mut state = CacheState::default();
+        let newer = (br#"{"_v":99,"h":"b","m":"gm","t":20}"#.to_vec(), 20);
+        state.insert(vec![member("a", 10), newer, member("c", 30)]).unwrap();
+
+        assert_eq!(hashes(state.window(30, 0)), vec!["c", "a"]);
+        assert_eq!(parse_members(&[b"not a record".to_vec(), member("d", 40).0]).len(), 1);
+    }
+
+    #[test]
+    fn test_window_applies_overrides() {
+        let mut state = CacheState::default();
+        state.insert(vec![member("a", 10), member("b", 20)]).unwrap();
+        state.overrides.insert("a".to_string(), Override::Hidden);
+
+        let moderations: Vec<_> = state.window(20, 0).into_iter().map(|tx| tx.moderation).collect();
+        assert_eq!(moderations, vec![None, Some(Moderation::Hidden)]);
+    }
+
+    #[test]
+    fn test_legacy_members() {
+        let legacy =
+            r#"[{"h":"a","m":"gm","t":10,"from":null,"to":null},{"h":"b","m":"gn","t":10,"from":null,"to":null}]"#;
+
+        let mut state = CacheState::default();
+        state
+            .insert(vec![(legacy.as_bytes().to_vec(), 10), member("c", 20)])
+            .unwrap();
+
+        assert_eq!(hashes(state.window(20, 0)), vec!["c", "a", "b"]);
+    }
+}
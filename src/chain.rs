@@ -0,0 +1,124 @@
+use anyhow::Result;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+const DEFAULT_CHAIN: &str = "mainnet";
+
+// (id, name, explorer url) of the chains that can be referred to by name or id alone
+const KNOWN_CHAINS: &[(u64, &str, &str)] = &[
+    (1, "mainnet", "https://etherscan.io"),
+    (10, "optimism", "https://optimistic.etherscan.io"),
+    (56, "bsc", "https://bscscan.com"),
+    (137, "polygon", "https://polygonscan.com"),
+    (8453, "base", "https://basescan.org"),
+    (42161, "arbitrum", "https://arbiscan.io"),
+    (17000, "holesky", "https://holesky.etherscan.io"),
+    (11155111, "sepolia", "https://sepolia.etherscan.io"),
+];
+
+/// An EVM chain whose transactions are scanned
+#[derive(Serialize, Clone, Debug, PartialEq, Eq, ToSchema)]
+pub struct Chain {
+    /// Chain id, as defined by EIP-155
+    pub id: u64,
+    /// Name used by the `chain` query parameter
+    pub name: String,
+    /// Block explorer, showing transactions at `{explorer_url}/tx/{hash}`
+    pub explorer_url: String,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Self::parse(DEFAULT_CHAIN).expect("The default chain is known")
+    }
+}
+
+impl Chain {
+    // Accepts the name or the id of a known chain, or `<id>:<name>:<explorer url>` for any other one
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+
+        if let Some((id, name, explorer_url)) = KNOWN_CHAINS
+            .iter()
+            .find(|(id, name, _)| *name == value || id.to_string() == value)
+        {
+            return Ok(Self {
+                id: *id,
+                name: name.to_string(),
+                explorer_url: explorer_url.to_string(),
+            });
+        }
+
+        let mut parts = value.splitn(3, ':');
+        let (id, name, explorer_url) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(name), Some(explorer_url)) => (id, name, explorer_url),
+            _ => return Err(anyhow::anyhow!("Unknown chain {}", value)),
+        };
+
+        // Names end up in Redis keys and URLs
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            return Err(anyhow::anyhow!("Invalid chain name {}", name));
+        }
+
+        Ok(Self {
+            id: id.parse()?,
+            name: name.to_string(),
+            explorer_url: explorer_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    // Chain scanned by this process, from `CHAIN`
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("CHAIN") {
+            Ok(value) => Self::parse(&value),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    // Chains served by this process, from the comma separated `CHAINS`. The first one is the default
+    pub fn list_from_env() -> Result<Vec<Self>> {
+        let chains = match std::env::var("CHAINS") {
+            Ok(value) => value
+                .split(',')
+                .filter(|item| !item.trim().is_empty())
+                .map(Self::parse)
+                .collect::<Result<Vec<Self>>>()?,
+            Err(_) => vec![],
+        };
+
+        if chains.is_empty() {
+            return Ok(vec![Self::default()]);
+        }
+
+        Ok(chains)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mainnet = Chain::parse("mainnet").unwrap();
+        assert_eq!(mainnet.id, 1);
+        assert_eq!(mainnet.explorer_url, "https://etherscan.io");
+        assert_eq!(Chain::parse("1").unwrap(), mainnet);
+        assert_eq!(Chain::default(), mainnet);
+
+        assert_eq!(Chain::parse(" 8453 ").unwrap().name, "base");
+
+        let custom = Chain::parse("100:gnosis:https://gnosisscan.io/").unwrap();
+        assert_eq!(custom.id, 100);
+        assert_eq!(custom.name, "gnosis");
+        assert_eq!(custom.explorer_url, "https://gnosisscan.io");
+
+        assert!(Chain::parse("unknown").is_err());
+        assert!(Chain::parse("x:gnosis:https://gnosisscan.io").is_err());
+        assert!(Chain::parse("100:Gnosis Chain:https://gnosisscan.io").is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use crate::cache::parse_member;
+use crate::chain::Chain;
+use crate::codec::Format;
+use crate::redis;
+use crate::schema;
+use crate::transaction::Transaction;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const SECONDS_IN_DAY: u64 = 86400;
+// Transactions are kept for a day unless told otherwise
+const DEFAULT_RETENTION: u64 = SECONDS_IN_DAY;
+// Default level of zstd
+const ARCHIVE_COMPRESSION_LEVEL: i32 = 0;
+// Seconds a run holds the lock of a chain at most, so that an instance dying mid-run
+// doesn't keep the other ones from cleaning
+const LOCK_TTL: u64 = 3600;
+
+// What a cleaning run did, or would have done on a dry run
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Report {
+    // Transactions older than the retention
+    pub expired: usize,
+    // Transactions written to the archive
+    pub archived: usize,
+    // Archive files written to, or that would have been
+    pub archives: Vec<PathBuf>,
+    // Members removed from the set, blocks stored before the index holding several transactions
+    pub removed: u64,
+    // Expired members that could not be parsed, left in place when archiving
+    pub unreadable: u64,
+    // Generation of the set after the removal
+    pub generation: Option<u64>,
+}
+
+// Removes the transactions older than `retention` seconds, optionally writing them to
+// archive files first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cleaner {
+    pub retention: u64,
+    // Only report what would be archived and removed
+    pub dry_run: bool,
+    // Directory of the archive files, expiring transactions are dropped when unset
+    pub archive_dir: Option<PathBuf>,
+}
+
+impl Default for Cleaner {
+    fn default() -> Self {
+        Self {
+            retention: DEFAULT_RETENTION,
+            dry_run: false,
+            archive_dir: None,
+        }
+    }
+}
+
+// Civil date of a timestamp in UTC, from http://howardhinnant.github.io/date_algorithms.html
+fn date(timestamp: u64) -> String {
+    let days = timestamp / SECONDS_IN_DAY + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Archive files are rotated daily, `<dir>/<chain>/<YYYY-MM-DD>.ndjson.zst` holding the
+// transactions of that day
+fn archive_path(dir: &Path, chain: &Chain, timestamp: u64) -> PathBuf {
+    dir.join(&chain.name).join(format!("{}.ndjson.zst", date(timestamp)))
+}
+
+fn archive_files<'a>(
+    dir: &Path,
+    chain: &Chain,
+    transactions: &'a [Transaction],
+) -> BTreeMap<PathBuf, Vec<&'a Transaction>> {
+    let mut files: BTreeMap<PathBuf, Vec<&Transaction>> = BTreeMap::new();
+    for tx in transactions {
+        files
+            .entry(archive_path(dir, chain, tx.timestamp))
+            .or_default()
+            .push(tx);
+    }
+
+    files
+}
+
+// Appends one JSON record per line to the archive files, in the versioned layout of the
+// stored ones. Every run appends a zstd frame, and concatenated frames decompress as one file
+pub fn archive(dir: &Path, chain: &Chain, transactions: &[Transaction]) -> Result<Vec<PathBuf>> {
+    let files = archive_files(dir, chain, transactions);
+
+    for (path, transactions) in files.iter() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut encoder = zstd::Encoder::new(file, ARCHIVE_COMPRESSION_LEVEL)?;
+        for tx in transactions {
+            encoder.write_all(&schema::encode(tx, &Format::default())?)?;
+            encoder.write_all(b"\n")?;
+        }
+        encoder.finish()?.sync_all()?;
+    }
+
+    Ok(files.into_keys().collect())
+}
+
+impl Cleaner {
+    // Retention in seconds from `CLEANER_RETENTION`, archives written to `CLEANER_ARCHIVE_DIR`
+    // when set, and dry runs when `CLEANER_DRY_RUN` is `true`
+    pub fn from_env() -> Result<Self> {
+        let retention = match std::env::var("CLEANER_RETENTION") {
+            Ok(retention) => retention
+                .parse()
+                .map_err(|_| anyhow::anyhow!("CLEANER_RETENTION must be a number of seconds"))?,
+            Err(_) => DEFAULT_RETENTION,
+        };
+
+        let dry_run = match std::env::var("CLEANER_DRY_RUN") {
+            Ok(dry_run) => dry_run
+                .parse()
+                .map_err(|_| anyhow::anyhow!("CLEANER_DRY_RUN must be true or false"))?,
+            Err(_) => false,
+        };
+
+        let archive_dir = match std::env::var("CLEANER_ARCHIVE_DIR") {
+            Ok(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+            _ => None,
+        };
+
+        Ok(Self {
+            retention,
+            dry_run,
+            archive_dir,
+        })
+    }
+
+    // Archives then removes the transactions of `chain` that expired at `now`. The removal
+    // is skipped when the archive could not be written, so that nothing is lost. Only the
+    // members archived are removed, ones that can't be read are left in place
+    pub async fn clean(&self, chain: &Chain, now: u64) -> Result<Report> {
+        let max = now.saturating_sub(self.retention);
+        let mut report = Report::default();
+
+        // Members read, removed once archived
+        let mut members = vec![];
+        if self.dry_run || self.archive_dir.is_some() {
+            let mut transactions = vec![];
+            for (member, value) in redis::members_up_to(chain, max).await? {
+                match parse_member(&value) {
+                    Ok(parsed) => {
+                        transactions.extend(parsed);
+                        members.push((member, value));
+                    }
+                    Err(error) => {
+                        log::warn!(
+                            "Leaving a member of {} that could not be parsed: {:?}",
+                            chain.name,
+                            error
+                        );
+                        report.unreadable += 1;
+                    }
+                }
+            }
+            report.expired = transactions.len();
+
+            report.archives = match &self.archive_dir {
+                Some(dir) if self.dry_run => archive_files(dir, chain, &transactions).into_keys().collect(),
+                Some(dir) => {
+                    let archives = archive(dir, chain, &transactions)?;
+                    report.archived = transactions.len();
+                    archives
+                }
+                None => vec![],
+            };
+        }
+
+        if self.dry_run {
+            log::info!(
+                "Dry run: would remove {} transactions of {} older than {}",
+                report.expired,
+                chain.name,
+                max
+            );
+            for path in report.archives.iter() {
+                log::info!("Dry run: would archive to {}", path.display());
+            }
+
+            return Ok(report);
+        }
+
+        if self.archive_dir.is_some() {
+            log::info!(
+                "Archived {} transactions of {} to {} files",
+                report.archived,
+                chain.name,
+                report.archives.len()
+            );
+
+            // Transactions stored since the read are kept for the next run
+            report.removed = redis::remove_members(chain, &members).await?;
+        } else {
+            report.removed = redis::zremrange_by_score(chain, max).await?;
+        }
+        log::info!("Removed {} values from the {} set", report.removed, chain.name);
+        if report.unreadable > 0 {
+            log::warn!("Left {} unreadable values in the {} set", report.unreadable, chain.name);
+        }
+
+        // Let the API processes know that their cached copy of the set is stale
+        let generation = redis::incr_generation(chain).await?;
+        log::info!("Bumped {} set generation to {}", chain.name, generation);
+        report.generation = Some(generation);
+
+        Ok(report)
+    }
+
+    // Cleans `chain` unless another instance, identified by another `token`, is already
+    // cleaning it, in which case nothing is done and `None` is returned. The outcome of
+    // every run is counted in the cleaner status of the chain
+    pub async fn clean_exclusively(&self, chain: &Chain, now: u64, token: &str) -> Result<Option<Report>> {
+        // Dry runs change nothing
+        if self.dry_run {
+            return Ok(Some(self.clean(chain, now).await?));
+        }
+
+        if !redis::acquire_cleaner_lock(chain, token, LOCK_TTL).await? {
+            log::info!("Another instance is cleaning {}, skipping", chain.name);
+            redis::incr_cleaner_status(chain, "skipped", 1).await?;
+            return Ok(None);
+        }
+
+        let started_at = Instant::now();
+        let result = self.clean(chain, now).await;
+        let duration = started_at.elapsed();
+
+        if !redis::release_cleaner_lock(chain, token).await? {
+            log::warn!("The cleaning lock of {} expired during the run", chain.name);
+        }
+
+        redis::incr_cleaner_status(chain, "runs", 1).await?;
+        redis::set_cleaner_status(chain, "last_run_at", now.to_string()).await?;
+        redis::set_cleaner_status(chain, "last_duration_ms", duration.as_millis().to_string()).await?;
+
+        match result {
+            Ok(report) => {
+                log::info!(
+                    "Cleaned {} in {} ms, removed {} values",
+                    chain.name,
+                    duration.as_millis(),
+                    report.removed
+                );
+                redis::incr_cleaner_status(chain, "removed", report.removed).await?;
+                redis::incr_cleaner_status(chain, "archived", report.archived as u64).await?;
+                redis::incr_cleaner_status(chain, "unreadable", report.unreadable).await?;
+                redis::set_cleaner_status(chain, "last_removed", report.removed.to_string()).await?;
+                redis::set_cleaner_status(chain, "last_error", String::new()).await?;
+
+                Ok(Some(report))
+            }
+            Err(error) => {
+                redis::incr_cleaner_status(chain, "failures", 1).await?;
+                redis::set_cleaner_status(chain, "last_error", error.to_string()).await?;
+
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_date() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951782400), "2000-02-29");
+        assert_eq!(date(1700000000), "2023-11-14");
+        assert_eq!(date(1704067199), "2023-12-31");
+        assert_eq!(date(1704067200), "2024-01-01");
+    }
+
+    #[test]
+    fn test_archive() {
+        let dir = std::env::temp_dir().join(format!("interprether-archive-{}", std::process::id()));
+        let chain = Chain::default();
+        let transaction = |hash: &str, timestamp| Transaction {
+            hash: hash.to_string(),
+            message: "gm".to_string(),
+            timestamp,
+            ..Default::default()
+        };
+
+        let paths = archive(
+            &dir,
+            &chain,
+            &[transaction("0x1", 1700000000), transaction("0x2", 1700090000)],
+        )
+        .unwrap();
+        assert_eq!(
+            paths,
+            vec![
+                dir.join("mainnet/2023-11-14.ndjson.zst"),
+                dir.join("mainnet/2023-11-15.ndjson.zst")
+            ]
+        );
+
+        // A later run appends to the file of the same day
+        archive(&dir, &chain, &[transaction("0x3", 1700000001)]).unwrap();
+
+        let mut text = String::new();
+        zstd::Decoder::new(std::fs::File::open(&paths[0]).unwrap())
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        let hashes: Vec<String> = text
+            .lines()
+            .flat_map(|line| schema::decode(line.as_bytes()).unwrap())
+            .map(|tx| tx.hash)
+            .collect();
+        assert_eq!(hashes, vec!["0x1", "0x3"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+// Frames written by zstd start with these bytes
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "json" => Ok(Encoding::Json),
+            "msgpack" => Ok(Encoding::MessagePack),
+            _ => Err(anyhow::anyhow!("Unknown encoding {}, expected json or msgpack", value)),
+        }
+    }
+}
+
+// How records are written to Redis. Records are read back whatever the format they were
+// written with, so that the format can be changed without migrating the stored ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Format {
+    pub encoding: Encoding,
+    // zstd compression level, records are left uncompressed when unset
+    pub compression: Option<i32>,
+}
+
+impl Format {
+    // From `STORAGE_ENCODING` (`json` or `msgpack`) and `STORAGE_COMPRESSION` (`none`, `zstd`
+    // or `zstd:<level>`), JSON without compression by default
+    pub fn from_env() -> Result<Self> {
+        let encoding = match std::env::var("STORAGE_ENCODING") {
+            Ok(encoding) => encoding.parse()?,
+            Err(_) => Encoding::default(),
+        };
+
+        let compression = match std::env::var("STORAGE_COMPRESSION").as_deref() {
+            Err(_) | Ok("none") => None,
+            Ok("zstd") => Some(DEFAULT_ZSTD_LEVEL),
+            Ok(value) => match value.strip_prefix("zstd:") {
+                Some(level) => Some(level.parse()?),
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "Unknown compression {}, expected none, zstd or zstd:<level>",
+                        value
+                    ))
+                }
+            },
+        };
+
+        Ok(Self { encoding, compression })
+    }
+}
+
+pub fn encode(record: &Map<String, Value>, format: &Format) -> Result<Vec<u8>> {
+    let encoded = match format.encoding {
+        Encoding::Json => serde_json::to_vec(record)?,
+        Encoding::MessagePack => rmp_serde::to_vec_named(record)?,
+    };
+
+    match format.compression {
+        Some(level) => Ok(zstd::encode_all(encoded.as_slice(), level)?),
+        None => Ok(encoded),
+    }
+}
+
+// JSON is told apart from MessagePack by its first byte, a record or an array of records
+// in JSON starting with `{` or `[` while MessagePack maps and arrays never do
+pub fn decode(bytes: &[u8]) -> Result<Value> {
+    if bytes.starts_with(&ZSTD_MAGIC) {
+        return decode(&zstd::decode_all(bytes)?);
+    }
+
+    match bytes.first() {
+        Some(b'{') | Some(b'[') => Ok(serde_json::from_slice(bytes)?),
+        Some(_) => Ok(rmp_serde::from_slice(bytes)?),
+        None => Err(anyhow::anyhow!("Empty record")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats() {
+        let record = match serde_json::json!({"_v": 2, "h": "0x1", "m": "gm", "t": 1700000000, "c": 0.5}) {
+            Value::Object(record) => record,
+            _ => unreachable!(),
+        };
+
+        for encoding in [Encoding::Json, Encoding::MessagePack] {
+            for compression in [None, Some(DEFAULT_ZSTD_LEVEL)] {
+                let format = Format { encoding, compression };
+                let encoded = encode(&record, &format).unwrap();
+
+                assert_eq!(decode(&encoded).unwrap(), Value::Object(record.clone()), "{:?}", format);
+            }
+        }
+
+        assert_eq!(
+            encode(&record, &Format::default()).unwrap(),
+            serde_json::to_vec(&record).unwrap()
+        );
+        assert!(decode(b"").is_err());
+        assert_eq!("msgpack".parse::<Encoding>().unwrap(), Encoding::MessagePack);
+        assert!("bincode".parse::<Encoding>().is_err());
+    }
+}
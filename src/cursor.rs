@@ -0,0 +1,111 @@
+use crate::transaction::Transaction;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// Position of a reader following the transactions as they are stored. Transactions are
+// stored with the timestamp of their block, which can be older than the time they are
+// captured at, and more of them can be stored later with the timestamp last seen, e.g.
+// event logs or blocks sharing a timestamp. So every read starts again from the last
+// timestamp seen, skipping the transactions already taken
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Cursor {
+    pub last_seen: u64,
+    // Ids of the transactions taken with the `last_seen` timestamp
+    #[serde(default)]
+    pub sent: HashSet<String>,
+}
+
+impl Cursor {
+    pub fn new(last_seen: u64) -> Self {
+        Self {
+            last_seen,
+            sent: HashSet::new(),
+        }
+    }
+
+    // Cursors used to be stored as the last timestamp read, every transaction up to it
+    // having been taken
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.parse::<u64>() {
+            Ok(timestamp) => Ok(Self::new(timestamp + 1)),
+            Err(_) => Ok(serde_json::from_str(value)?),
+        }
+    }
+
+    // Takes the transactions with a timestamp of at least `last_seen`, newest first, and
+    // returns the ones not taken yet in the order they were mined
+    pub fn advance(&mut self, transactions: Vec<Transaction>) -> Vec<Transaction> {
+        let mut unsent = vec![];
+        for tx in transactions.into_iter().rev() {
+            if tx.timestamp < self.last_seen || (tx.timestamp == self.last_seen && self.sent.contains(&tx.id())) {
+                continue;
+            }
+
+            if tx.timestamp > self.last_seen {
+                self.last_seen = tx.timestamp;
+                self.sent.clear();
+            }
+            self.sent.insert(tx.id());
+            unsent.push(tx);
+        }
+
+        unsent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(hash: &str, timestamp: u64) -> Transaction {
+        Transaction {
+            hash: hash.to_string(),
+            message: "gm".to_string(),
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    fn hashes(transactions: Vec<Transaction>) -> Vec<String> {
+        transactions.into_iter().map(|tx| tx.hash).collect()
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut cursor = Cursor::default();
+
+        assert_eq!(hashes(cursor.advance(vec![tx("0x1", 100)])), vec!["0x1"]);
+
+        // Newer blocks are taken in the order they were mined
+        assert_eq!(
+            hashes(cursor.advance(vec![tx("0x3", 200), tx("0x2", 150), tx("0x1", 100)])),
+            vec!["0x2", "0x3"]
+        );
+
+        // So is a log stored later with the timestamp already seen, but not the transaction
+        let mut log = tx("0x3", 200);
+        log.log_index = Some(0);
+        assert_eq!(hashes(cursor.advance(vec![log, tx("0x3", 200)])), vec!["0x3"]);
+        assert!(cursor.advance(vec![tx("0x3", 200)]).is_empty());
+    }
+
+    #[test]
+    fn test_saves_with_the_same_timestamp() {
+        // Two blocks sharing a timestamp, the second one stored after the first was read
+        let mut cursor = Cursor::new(100);
+        assert_eq!(hashes(cursor.advance(vec![tx("0xa", 300)])), vec!["0xa"]);
+        assert_eq!(
+            hashes(cursor.advance(vec![tx("0xb", 300), tx("0xa", 300)])),
+            vec!["0xb"]
+        );
+
+        // The ids taken survive a restart
+        let stored = serde_json::to_string(&cursor).unwrap();
+        let mut cursor = Cursor::parse(&stored).unwrap();
+        assert!(cursor.advance(vec![tx("0xb", 300), tx("0xa", 300)]).is_empty());
+
+        assert_eq!(Cursor::parse("1700000000").unwrap(), Cursor::new(1700000001));
+        assert!(Cursor::parse("soon").is_err());
+    }
+}
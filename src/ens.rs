@@ -0,0 +1,287 @@
+use crate::redis;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use web3::ethabi::{self, ParamType, Token};
+use web3::signing::keccak256;
+use web3::types::{Address, Bytes, CallRequest};
+
+// ENS registry, deployed at the same address on mainnet and testnets
+const ENS_REGISTRY: &str = "0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e";
+
+const DEFAULT_CACHE_TTL: u64 = 86400;
+// Lookups waiting for the resolver, the ones that don't fit are asked again by later requests
+const QUEUE_SIZE: usize = 1024;
+
+// Hash of a name as defined by EIP-137, e.g. `namehash("alice.eth")`
+pub fn namehash(name: &str) -> [u8; 32] {
+    let mut node = [0u8; 32];
+    if name.is_empty() {
+        return node;
+    }
+
+    for label in name.rsplit('.') {
+        node = keccak256(&[node, keccak256(label.as_bytes())].concat());
+    }
+
+    node
+}
+
+fn reverse_node(address: &Address) -> [u8; 32] {
+    namehash(&format!("{}.addr.reverse", hex::encode(address.as_bytes())))
+}
+
+fn encode_call(signature: &str, node: [u8; 32]) -> Vec<u8> {
+    let selector = &keccak256(signature.as_bytes())[..4];
+    [selector, &ethabi::encode(&[Token::FixedBytes(node.to_vec())])].concat()
+}
+
+// Reverse resolution of addresses through the ENS registry of a JSON-RPC provider
+pub struct EnsResolver {
+    web3: web3::Web3<web3::transports::Http>,
+    registry: Address,
+}
+
+impl EnsResolver {
+    pub fn new(provider_url: &str) -> Result<Self> {
+        Self::with_registry(provider_url, Address::from_str(ENS_REGISTRY)?)
+    }
+
+    pub fn with_registry(provider_url: &str, registry: Address) -> Result<Self> {
+        let transport = web3::transports::Http::new(provider_url)?;
+
+        Ok(Self {
+            web3: web3::Web3::new(transport),
+            registry,
+        })
+    }
+
+    async fn call(&self, to: Address, data: Vec<u8>, output: ParamType) -> Result<Option<Token>> {
+        let request = CallRequest {
+            to: Some(to),
+            data: Some(Bytes(data)),
+            ..Default::default()
+        };
+
+        let result = self.web3.eth().call(request, None).await?;
+        if result.0.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(ethabi::decode(&[output], &result.0)?.into_iter().next())
+    }
+
+    async fn resolver(&self, node: [u8; 32]) -> Result<Option<Address>> {
+        let token = self
+            .call(
+                self.registry,
+                encode_call("resolver(bytes32)", node),
+                ParamType::Address,
+            )
+            .await?;
+
+        Ok(token.and_then(Token::into_address).filter(|address| !address.is_zero()))
+    }
+
+    // Primary name of `address`. Names are only trusted when they resolve back to the address
+    pub async fn reverse(&self, address: Address) -> Result<Option<String>> {
+        let node = reverse_node(&address);
+        let resolver = match self.resolver(node).await? {
+            Some(resolver) => resolver,
+            None => return Ok(None),
+        };
+
+        let name = match self
+            .call(resolver, encode_call("name(bytes32)", node), ParamType::String)
+            .await?
+        {
+            Some(Token::String(name)) if !name.is_empty() => name,
+            _ => return Ok(None),
+        };
+
+        let node = namehash(&name);
+        let resolver = match self.resolver(node).await? {
+            Some(resolver) => resolver,
+            None => return Ok(None),
+        };
+
+        let resolved = self
+            .call(resolver, encode_call("addr(bytes32)", node), ParamType::Address)
+            .await?
+            .and_then(Token::into_address);
+
+        Ok(Some(name).filter(|_| resolved == Some(address)))
+    }
+}
+
+// Names of the addresses shown by the API. Requests only read the names cached in Redis,
+// addresses missing from the cache are resolved in the background and show up later
+#[derive(Clone)]
+pub struct NameService {
+    queue: mpsc::Sender<String>,
+    pending: Arc<Mutex<HashSet<String>>>,
+}
+
+impl NameService {
+    // Enabled by setting `ENS_PROVIDER_URL`, names are kept for `ENS_CACHE_TTL` seconds
+    pub fn from_env() -> Result<Option<Self>> {
+        let provider_url = match std::env::var("ENS_PROVIDER_URL") {
+            Ok(url) if !url.is_empty() => url,
+            _ => return Ok(None),
+        };
+
+        let ttl = match std::env::var("ENS_CACHE_TTL") {
+            Ok(ttl) => ttl.parse()?,
+            Err(_) => DEFAULT_CACHE_TTL,
+        };
+
+        Ok(Some(Self::spawn(EnsResolver::new(&provider_url)?, ttl)))
+    }
+
+    pub fn spawn(resolver: EnsResolver, ttl: u64) -> Self {
+        let (queue, mut receiver) = mpsc::channel::<String>(QUEUE_SIZE);
+        let pending: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
+
+        let worker_pending = pending.clone();
+        tokio::spawn(async move {
+            while let Some(address) = receiver.recv().await {
+                let name = match Address::from_str(&address) {
+                    Ok(parsed) => resolver.reverse(parsed).await,
+                    Err(error) => Err(error.into()),
+                };
+
+                // Addresses without a name are cached too, as an empty string
+                let result = match name {
+                    Ok(name) => redis::set_ens_name(&address, name.unwrap_or_default(), ttl).await,
+                    Err(error) => Err(error),
+                };
+
+                if let Err(error) = result {
+                    log::warn!("Could not resolve the name of {}: {:?}", address, error);
+                }
+
+                worker_pending.lock().unwrap().remove(&address);
+            }
+        });
+
+        Self { queue, pending }
+    }
+
+    // Cached names of `addresses`, queueing the unknown ones for resolution
+    pub async fn names(&self, addresses: HashSet<String>) -> Result<HashMap<String, String>> {
+        let addresses: Vec<String> = addresses.into_iter().map(|a| a.to_lowercase()).collect();
+        if addresses.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let cached = redis::get_ens_names(&addresses).await?;
+
+        let mut names = HashMap::new();
+        for (address, name) in addresses.into_iter().zip(cached) {
+            match name {
+                Some(name) if !name.is_empty() => {
+                    names.insert(address, name);
+                }
+                Some(_) => {}
+                None => self.queue_lookup(address),
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn queue_lookup(&self, address: String) {
+        let mut pending = self.pending.lock().unwrap();
+        // Marked as pending before being sent, so that the worker always finds it there
+        if !pending.insert(address.clone()) {
+            return;
+        }
+
+        if self.queue.try_send(address.clone()).is_err() {
+            pending.remove(&address);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    const RESOLVER: [u8; 20] = [0x11; 20];
+
+    fn selector(signature: &str) -> String {
+        hex::encode(&keccak256(signature.as_bytes())[..4])
+    }
+
+    // Answers eth_call like a registry whose names all point to RESOLVER, and a resolver
+    // returning `name` for every reverse record and `addr` for every name
+    async fn provider(name: &'static str, addr: Address) -> String {
+        let route = warp::post()
+            .and(warp::body::json())
+            .map(move |request: serde_json::Value| {
+                let data = request["params"][0]["data"].as_str().unwrap().trim_start_matches("0x");
+
+                let result = if data.starts_with(&selector("resolver(bytes32)")) {
+                    ethabi::encode(&[Token::Address(Address::from(RESOLVER))])
+                } else if data.starts_with(&selector("name(bytes32)")) {
+                    ethabi::encode(&[Token::String(name.to_string())])
+                } else if data.starts_with(&selector("addr(bytes32)")) {
+                    ethabi::encode(&[Token::Address(addr)])
+                } else {
+                    vec![]
+                };
+
+                warp::reply::json(&serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": format!("0x{}", hex::encode(result)),
+                }))
+            });
+
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn test_namehash() {
+        // Test vectors from EIP-137
+        assert_eq!(namehash(""), [0u8; 32]);
+        assert_eq!(
+            hex::encode(namehash("eth")),
+            "93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"
+        );
+        assert_eq!(
+            hex::encode(namehash("foo.eth")),
+            "de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reverse() {
+        let address = Address::repeat_byte(0xab);
+        let resolver = EnsResolver::new(&provider("alice.eth", address).await).unwrap();
+
+        assert_eq!(resolver.reverse(address).await.unwrap().as_deref(), Some("alice.eth"));
+    }
+
+    #[tokio::test]
+    async fn test_reverse_without_name() {
+        let address = Address::repeat_byte(0xab);
+        let resolver = EnsResolver::new(&provider("", address).await).unwrap();
+
+        assert_eq!(resolver.reverse(address).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_reverse_rejects_names_of_other_addresses() {
+        let address = Address::repeat_byte(0xab);
+        let resolver = EnsResolver::new(&provider("vitalik.eth", Address::repeat_byte(0xcd)).await).unwrap();
+
+        assert_eq!(resolver.reverse(address).await.unwrap(), None);
+    }
+}
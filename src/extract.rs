@@ -0,0 +1,642 @@
+use crate::abi;
+use crate::transaction::Encoding;
+use anyhow::Result;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE};
+use base64::Engine;
+
+const DEFAULT_MIN_PRINTABLE_RATIO: f32 = 0.9;
+const DEFAULT_MIN_LENGTH: usize = 1;
+
+// Shortest text taken for hex or base64 when it has no other hint, so that
+// short words made of the right letters (e.g. "cafe", "test") are left alone
+const MIN_UNPREFIXED_HEX_LENGTH: usize = 16;
+const MIN_BASE64_LENGTH: usize = 12;
+
+// Media type of `data:` URIs that don't have one
+const DEFAULT_MEDIA_TYPE: &str = "text/plain";
+
+// Decides which input data is worth showing as a message
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtractorConfig {
+    // Minimum share of printable characters, NUL bytes excluded, for the input to be accepted
+    pub min_printable_ratio: f32,
+    // Minimum number of characters of the cleaned message
+    pub min_length: usize,
+    // Whether input that is not entirely valid UTF-8 can still be decoded
+    pub allow_lossy: bool,
+}
+
+impl Default for ExtractorConfig {
+    fn default() -> Self {
+        Self {
+            min_printable_ratio: DEFAULT_MIN_PRINTABLE_RATIO,
+            min_length: DEFAULT_MIN_LENGTH,
+            allow_lossy: true,
+        }
+    }
+}
+
+impl ExtractorConfig {
+    // From `EXTRACT_MIN_PRINTABLE_RATIO` (from 0 to 1), `EXTRACT_MIN_LENGTH` and
+    // `EXTRACT_ALLOW_LOSSY`, each falling back to its default when unset
+    pub fn from_env() -> Result<Self> {
+        let default = Self::default();
+
+        let min_printable_ratio = match std::env::var("EXTRACT_MIN_PRINTABLE_RATIO") {
+            Ok(ratio) => match ratio.parse::<f32>() {
+                Ok(ratio) if (0.0..=1.0).contains(&ratio) => ratio,
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "EXTRACT_MIN_PRINTABLE_RATIO must be a number from 0 to 1"
+                    ))
+                }
+            },
+            Err(_) => default.min_printable_ratio,
+        };
+
+        let min_length = match std::env::var("EXTRACT_MIN_LENGTH") {
+            Ok(length) => length
+                .parse()
+                .map_err(|_| anyhow::anyhow!("EXTRACT_MIN_LENGTH must be a number of characters"))?,
+            Err(_) => default.min_length,
+        };
+
+        let allow_lossy = match std::env::var("EXTRACT_ALLOW_LOSSY") {
+            Ok(allow) => allow
+                .parse()
+                .map_err(|_| anyhow::anyhow!("EXTRACT_ALLOW_LOSSY must be true or false"))?,
+            Err(_) => default.allow_lossy,
+        };
+
+        Ok(Self {
+            min_printable_ratio,
+            min_length,
+            allow_lossy,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Extracted {
+    pub message: String,
+    // Share of printable characters in the decoded input, from 0 to 1
+    pub confidence: f32,
+    // Function argument the message was decoded from, when the input is a contract call
+    pub argument: Option<String>,
+    // Encoding the message was decoded from
+    pub encoding: Encoding,
+    // Set when the message is a `data:` URI
+    pub data_uri: Option<DataUri>,
+    // ISO 639-3 code of the language of the message, when it could be told
+    pub lang: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataUri {
+    pub media_type: String,
+    // Size of the decoded payload in bytes
+    pub size: u64,
+}
+
+// Language of a message, using the trigram models bundled with whatlang. Messages that are
+// too short or mix too many languages are left without one
+pub fn detect_language(message: &str) -> Option<String> {
+    whatlang::detect(message)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang().code().to_string())
+}
+
+// Parses `data:[<media type>][;<parameter>...][;base64],<payload>` as described in RFC 2397.
+// Payloads that are not valid for their encoding make the message plain text
+pub fn parse_data_uri(text: &str) -> Option<DataUri> {
+    let prefix = text.get(..5)?;
+    if !prefix.eq_ignore_ascii_case("data:") {
+        return None;
+    }
+
+    let (header, payload) = text[5..].split_once(',')?;
+    let mut parameters = header.split(';');
+
+    let media_type = match parameters.next().map(|t| t.trim().to_ascii_lowercase()) {
+        Some(media_type) if media_type.is_empty() => DEFAULT_MEDIA_TYPE.to_string(),
+        Some(media_type) if is_media_type(&media_type) => media_type,
+        _ => return None,
+    };
+
+    let size = if parameters.any(|p| p.trim().eq_ignore_ascii_case("base64")) {
+        let payload: String = payload.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+        STANDARD.decode(payload).ok()?.len()
+    } else {
+        percent_decoded_size(payload)
+    };
+
+    Some(DataUri {
+        media_type,
+        size: size as u64,
+    })
+}
+
+// Number of bytes of a percent-encoded payload. A `%` that is not followed by two hex
+// digits is a literal character
+fn percent_decoded_size(payload: &str) -> usize {
+    let bytes = payload.as_bytes();
+    let mut size = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes[index] == b'%'
+            && bytes.get(index + 1).is_some_and(u8::is_ascii_hexdigit)
+            && bytes.get(index + 2).is_some_and(u8::is_ascii_hexdigit);
+        index += if escaped { 3 } else { 1 };
+        size += 1;
+    }
+
+    size
+}
+
+fn is_media_type(text: &str) -> bool {
+    let is_token = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+
+    matches!(text.split_once('/'), Some((kind, subtype)) if is_token(kind) && is_token(subtype))
+}
+
+fn is_kept_control(c: char) -> bool {
+    c == '\n' || c == '\t'
+}
+
+pub fn extract_message(input: &[u8], config: &ExtractorConfig) -> Result<Extracted> {
+    // Contract calls are only shown when one of their arguments is text, picking the longest one
+    if abi::is_call(input) {
+        return abi::text_arguments(input)
+            .into_iter()
+            .filter_map(|(argument, bytes)| {
+                extract_text(&bytes, config).ok().map(|extracted| Extracted {
+                    argument: Some(argument),
+                    ..extracted
+                })
+            })
+            .max_by_key(|extracted| extracted.message.len())
+            .ok_or_else(|| anyhow::anyhow!("Contract call without text arguments"));
+    }
+
+    extract_text(input, config)
+}
+
+// Text carried by the string and bytes values of an event log, picking the longest one
+pub fn extract_log_message(topics: &[[u8; 32]], data: &[u8], config: &ExtractorConfig) -> Result<Extracted> {
+    abi::log_text_arguments(topics, data)
+        .into_iter()
+        .filter_map(|(argument, bytes)| {
+            extract_text(&bytes, config).ok().map(|extracted| Extracted {
+                argument: Some(argument),
+                ..extracted
+            })
+        })
+        .max_by_key(|extracted| extracted.message.len())
+        .ok_or_else(|| anyhow::anyhow!("Log without text values"))
+}
+
+// Decoder pipeline: UTF-16 is recognized on the raw bytes, anything else is read as UTF-8
+// and then unwrapped once more when the resulting text is itself hex or base64
+fn extract_text(input: &[u8], config: &ExtractorConfig) -> Result<Extracted> {
+    if let Some(decoded) = decode_utf16(input) {
+        if let Ok(extracted) = clean(&decoded, Encoding::Utf16, config) {
+            return Ok(extracted);
+        }
+    }
+
+    let extracted = clean(&decode_utf8(input, config)?, Encoding::Utf8, config)?;
+
+    let unwrapped = [
+        (Encoding::Hex, decode_hex(&extracted.message)),
+        (Encoding::Base64, decode_base64(&extracted.message)),
+    ];
+    for (encoding, bytes) in unwrapped {
+        let inner = bytes.and_then(|bytes| decode_utf8(&bytes, config).ok());
+        if let Some(Ok(inner)) = inner.map(|inner| clean(&inner, encoding, config)) {
+            return Ok(inner);
+        }
+    }
+
+    Ok(extracted)
+}
+
+fn decode_utf8(input: &[u8], config: &ExtractorConfig) -> Result<String> {
+    match std::str::from_utf8(input) {
+        Ok(decoded) => Ok(decoded.to_string()),
+        Err(_) if config.allow_lossy => Ok(String::from_utf8_lossy(input).into_owned()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+// UTF-16 input either starts with a byte order mark, or is mostly made of characters in the
+// ASCII range, which leave every other byte set to zero
+fn decode_utf16(input: &[u8]) -> Option<String> {
+    // Ignore the NUL padding, keeping the length even
+    let end = input.iter().rposition(|b| *b != 0).map(|p| p + 1).unwrap_or(0);
+    let end = end + end % 2;
+    if end < 4 || end > input.len() {
+        return None;
+    }
+
+    let (little_endian, body) = match input[..2] {
+        [0xff, 0xfe] => (true, &input[2..end]),
+        [0xfe, 0xff] => (false, &input[2..end]),
+        _ => {
+            let body = &input[..end];
+            let units = body.len() / 2;
+            let even_zeros = body.iter().step_by(2).filter(|b| **b == 0).count();
+            let odd_zeros = body.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+
+            if odd_zeros * 10 >= units * 9 && even_zeros * 10 <= units {
+                (true, body)
+            } else if even_zeros * 10 >= units * 9 && odd_zeros * 10 <= units {
+                (false, body)
+            } else {
+                return None;
+            }
+        }
+    };
+
+    let units: Vec<u16> = body
+        .chunks_exact(2)
+        .map(|unit| match little_endian {
+            true => u16::from_le_bytes([unit[0], unit[1]]),
+            false => u16::from_be_bytes([unit[0], unit[1]]),
+        })
+        .collect();
+
+    Some(String::from_utf16_lossy(&units))
+}
+
+// Text made of hex digits, prefixed with 0x or long enough not to be a word
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    let (digits, min_length) = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => (digits, 2),
+        None => (text, MIN_UNPREFIXED_HEX_LENGTH),
+    };
+
+    if digits.len() < min_length || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    hex::decode(digits).ok()
+}
+
+// Padded base64, in either the standard or the URL-safe alphabet
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    if text.len() < MIN_BASE64_LENGTH {
+        return None;
+    }
+
+    STANDARD.decode(text).or_else(|_| URL_SAFE.decode(text)).ok()
+}
+
+// Drops the characters that can't be shown and checks what is left is worth showing
+fn clean(decoded: &str, encoding: Encoding, config: &ExtractorConfig) -> Result<Extracted> {
+    // NUL bytes are commonly used as padding, so they don't count as garbage
+    let mut total = 0;
+    let mut printable = 0;
+    let mut message = String::with_capacity(decoded.len());
+    for c in decoded.chars().filter(|c| *c != char::from(0)) {
+        total += 1;
+
+        if c == char::REPLACEMENT_CHARACTER || (c.is_control() && !is_kept_control(c)) {
+            continue;
+        }
+
+        printable += 1;
+        message.push(c);
+    }
+
+    let message = message.trim().to_string();
+    if message.is_empty() {
+        return Err(anyhow::anyhow!("Empty input data"));
+    }
+
+    if message.chars().count() < config.min_length {
+        return Err(anyhow::anyhow!("Message too short"));
+    }
+
+    let confidence = printable as f32 / total as f32;
+    if confidence < config.min_printable_ratio {
+        return Err(anyhow::anyhow!("Not enough printable characters ({:.2})", confidence));
+    }
+
+    let data_uri = parse_data_uri(&message);
+    let lang = match data_uri {
+        Some(_) => None,
+        None => detect_language(&message),
+    };
+
+    Ok(Extracted {
+        data_uri,
+        lang,
+        message,
+        confidence,
+        argument: None,
+        encoding,
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+
+    fn extract(input: &[u8]) -> Result<Extracted> {
+        extract_message(input, &ExtractorConfig::default())
+    }
+
+    #[test]
+    fn test_empty_message() {
+        let input: Vec<u8> = vec![];
+        assert_eq!(extract(&input).is_err(), true);
+    }
+
+    #[test]
+    fn test_null_message() {
+        let input: Vec<u8> = vec![0u8];
+        assert_eq!(extract(&input).is_err(), true);
+    }
+
+    #[test]
+    fn test_message_with_empty_chars() {
+        let input: Vec<u8> = vec![0u8, 32u8, 0u8];
+        assert_eq!(extract(&input).is_err(), true);
+    }
+
+    #[test]
+    fn test_message_with_message() {
+        let input: Vec<u8> = vec![0u8, 32u8, 104u8, 101u8, 108u8, 108u8, 111u8, 33u8, 32u8, 0u8];
+        assert_eq!(extract(&input).unwrap().message, "hello!");
+    }
+
+    #[test]
+    fn test_real_world_inputs() {
+        let erc20_transfer = [
+            &[0xa9, 0x05, 0x9c, 0xbb][..],
+            &[0; 12],
+            &[0x3f, 0x5c, 0xe5, 0xfb, 0xfe, 0x3e, 0x9a, 0xf3, 0x97, 0x1d],
+            &[0xd8, 0x33, 0xd2, 0x6b, 0xa9, 0xb5, 0xc9, 0x36, 0xf0, 0xbe],
+            &[0; 28],
+            &[0x05, 0xf5, 0xe1, 0x00],
+        ]
+        .concat();
+
+        // (input, expected message, minimum confidence)
+        let table: Vec<(Vec<u8>, Option<&str>, f32)> = vec![
+            (b"gm".to_vec(), Some("gm"), 1.0),
+            (b"Hello, world!\0\0\0\0\0\0".to_vec(), Some("Hello, world!"), 1.0),
+            ("比特币万岁".as_bytes().to_vec(), Some("比特币万岁"), 1.0),
+            ("Привет, мир 🚀".as_bytes().to_vec(), Some("Привет, мир 🚀"), 1.0),
+            (
+                b"line one\r\nline two\tend".to_vec(),
+                Some("line one\nline two\tend"),
+                0.9,
+            ),
+            // Some stray bytes in an otherwise readable message
+            (
+                b"Please return my funds \xff to the original address, thank you".to_vec(),
+                Some("Please return my funds  to the original address, thank you"),
+                0.95,
+            ),
+            // Terminal escape sequences and other control characters
+            (b"\x1b[31mred alert\x1b[0m".to_vec(), Some("[31mred alert[0m"), 0.8),
+            (b"\x01\x02\x03\x04\x05abc".to_vec(), None, 0.0),
+            (erc20_transfer, None, 0.0),
+            (vec![0xde, 0xad, 0xbe, 0xef], None, 0.0),
+            (b"\0\0\0\0\0\0".to_vec(), None, 0.0),
+        ];
+
+        let config = ExtractorConfig {
+            min_printable_ratio: 0.8,
+            ..Default::default()
+        };
+
+        for (input, expected, min_confidence) in table.iter() {
+            let result = extract_message(input, &config);
+
+            match expected {
+                Some(message) => {
+                    let extracted = result.unwrap_or_else(|e| panic!("{:?} was rejected: {:?}", input, e));
+                    assert_eq!(&extracted.message, message);
+                    assert!(extracted.confidence >= *min_confidence, "{:?}", extracted);
+                }
+                None => assert!(result.is_err(), "{:?} was accepted: {:?}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_contract_calls() {
+        let set_name = [
+            &[0xc4, 0x7f, 0x00, 0x27][..],
+            &[0; 31],
+            &[0x20],
+            &[0; 31],
+            &[0x09],
+            b"alice.eth",
+            &[0; 23],
+        ]
+        .concat();
+
+        let extracted = extract(&set_name).unwrap();
+        assert_eq!(extracted.message, "alice.eth");
+        assert_eq!(extracted.argument.as_deref(), Some("setName(string)[0]"));
+
+        // Selector and arguments of a transfer that happen to be valid UTF-8
+        let transfer = [&[0xa9, 0x05, 0x9c, 0xbb][..], &[0; 12], &[0x41; 20], &[0; 30], b"AA"].concat();
+        assert!(extract(&transfer).is_err());
+
+        assert!(extract(b"gm").unwrap().argument.is_none());
+    }
+
+    #[test]
+    fn test_strict_utf8() {
+        let config = ExtractorConfig {
+            allow_lossy: false,
+            ..Default::default()
+        };
+
+        assert!(extract_message(b"almost text \xff", &config).is_err());
+        assert!(extract_message(b"almost text \xff", &ExtractorConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_min_length() {
+        let config = ExtractorConfig {
+            min_length: 3,
+            ..Default::default()
+        };
+
+        assert!(extract_message(b"gm", &config).is_err());
+        assert!(extract_message(b"gm!", &config).is_ok());
+    }
+
+    fn utf16(text: &str, little_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| match little_endian {
+                true => unit.to_le_bytes(),
+                false => unit.to_be_bytes(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_utf16() {
+        let extracted = extract(&utf16("Hello, world!", true)).unwrap();
+        assert_eq!(extracted.message, "Hello, world!");
+        assert_eq!(extracted.encoding, Encoding::Utf16);
+
+        assert_eq!(
+            extract(&utf16("Hello, world!", false)).unwrap().encoding,
+            Encoding::Utf16
+        );
+
+        // Non ASCII text is only recognized with a byte order mark
+        let with_bom = [&[0xff, 0xfe][..], &utf16("比特币万岁", true)].concat();
+        let extracted = extract(&with_bom).unwrap();
+        assert_eq!(extracted.message, "比特币万岁");
+        assert_eq!(extracted.encoding, Encoding::Utf16);
+
+        // Trailing NUL padding is ignored
+        let padded = [&utf16("gm", true)[..], &[0; 27]].concat();
+        assert_eq!(extract(&padded).unwrap().encoding, Encoding::Utf16);
+
+        // Unpaired surrogates are garbage
+        let surrogates = [0xff, 0xfe, 0x00, 0xd8, 0x00, 0xd8, 0x00, 0xd8];
+        assert!(extract(&surrogates).is_err());
+
+        assert_eq!(extract(b"gm").unwrap().encoding, Encoding::Utf8);
+    }
+
+    #[test]
+    fn test_hex() {
+        let extracted = extract(b"0x48656c6c6f2c20776f726c6421").unwrap();
+        assert_eq!(extracted.message, "Hello, world!");
+        assert_eq!(extracted.encoding, Encoding::Hex);
+
+        assert_eq!(extract(b"48656c6c6f2c20776f726c6421").unwrap().encoding, Encoding::Hex);
+
+        // Hex whose bytes are not text, odd digits and short words are shown as they are
+        let table: [&[u8]; 4] = [b"0xdeadbeef", b"0x48656c6c6f2", b"cafe", b"0xZZ"];
+        for input in table {
+            let extracted = extract(input).unwrap();
+            assert_eq!(extracted.message.as_bytes(), input);
+            assert_eq!(extracted.encoding, Encoding::Utf8);
+        }
+    }
+
+    #[test]
+    fn test_base64() {
+        let extracted = extract(b"SGVsbG8sIHdvcmxkIQ==").unwrap();
+        assert_eq!(extracted.message, "Hello, world!");
+        assert_eq!(extracted.encoding, Encoding::Base64);
+
+        // URL-safe alphabet
+        let extracted = extract(b"PDw_Pz8-Pmdt").unwrap();
+        assert_eq!(extracted.message, "<<???>>gm");
+        assert_eq!(extracted.encoding, Encoding::Base64);
+
+        // Base64 whose bytes are not text, missing padding and short words are shown as they are
+        let table: [&[u8]; 4] = [b"3q2+7w3q2+7w", b"SGVsbG8sIHdvcmxkIQ", b"test", b"HappyNewYear"];
+        for input in table {
+            let extracted = extract(input).unwrap();
+            assert_eq!(extracted.message.as_bytes(), input);
+            assert_eq!(extracted.encoding, Encoding::Utf8);
+        }
+    }
+
+    #[test]
+    fn test_encoded_contract_argument() {
+        let set_name = [
+            &[0xc4, 0x7f, 0x00, 0x27][..],
+            &[0; 31],
+            &[0x20],
+            &[0; 31],
+            &[0x14],
+            b"YWxpY2UuZXRoLmV0aA==",
+            &[0; 12],
+        ]
+        .concat();
+
+        let extracted = extract(&set_name).unwrap();
+        assert_eq!(extracted.message, "alice.eth.eth");
+        assert_eq!(extracted.encoding, Encoding::Base64);
+        assert_eq!(extracted.argument.as_deref(), Some("setName(string)[0]"));
+    }
+
+    #[test]
+    fn test_data_uri() {
+        let extracted = extract(b"data:image/png;base64,iVBORw0KGgo=").unwrap();
+        assert_eq!(extracted.message, "data:image/png;base64,iVBORw0KGgo=");
+        assert_eq!(
+            extracted.data_uri,
+            Some(DataUri {
+                media_type: "image/png".to_string(),
+                size: 8,
+            })
+        );
+
+        // (uri, expected media type and size)
+        let table = [
+            ("data:,Hello%2C%20world", Some(("text/plain", 12))),
+            ("data:;base64,Z20=", Some(("text/plain", 2))),
+            (
+                "DATA:Application/JSON,{\"p\":\"erc-20\"}",
+                Some(("application/json", 14)),
+            ),
+            (
+                "data:image/svg+xml;charset=utf-8;base64,PHN2Zy8+",
+                Some(("image/svg+xml", 6)),
+            ),
+            ("data:text/plain;charset=utf-8,gm", Some(("text/plain", 2))),
+            ("data:,%", Some(("text/plain", 1))),
+            ("data:,%%", Some(("text/plain", 2))),
+            ("data:,100%25 %zz%4", Some(("text/plain", 10))),
+            ("data:image/png;base64,not base64!", None),
+            ("data:no media type,gm", None),
+            ("data:text/plain", None),
+            ("the data:,is elsewhere", None),
+            ("gm", None),
+        ];
+
+        for (uri, expected) in table {
+            let parsed = parse_data_uri(uri);
+            let parsed = parsed.as_ref().map(|d| (d.media_type.as_str(), d.size));
+            assert_eq!(parsed, expected, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let table = [
+            (
+                "Thank you for all of your help with the move, I will pay you back next week when I get paid",
+                Some("eng"),
+            ),
+            ("Съешь же ещё этих мягких французских булок, да выпей чаю", Some("rus")),
+            (
+                "比特币是一种基于去中心化的数字货币，采用点对点网络与共识机制",
+                Some("cmn"),
+            ),
+            (
+                "Falsches Üben von Xylophonmusik quält jeden größeren Zwerg",
+                Some("deu"),
+            ),
+            ("gm", None),
+            ("0x1234", None),
+        ];
+
+        for (message, expected) in table {
+            assert_eq!(detect_language(message).as_deref(), expected, "{}", message);
+        }
+
+        assert_eq!(extract(b"data:,Hello%2C%20world").unwrap().lang, None);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::transaction::{Kind, Transaction};
+use serde::{Deserialize, Serialize};
+
+fn same_address(a: &Option<String>, b: &str) -> bool {
+    a.as_ref().map(|a| a.eq_ignore_ascii_case(b)).unwrap_or(false)
+}
+
+// Criteria that transactions must all satisfy. Subscriptions are stored with theirs
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct TransactionFilter {
+    // Sender or recipient address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    // Timestamps, both inclusive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+    // Case insensitive text that the message must contain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<Kind>,
+    // ISO 639-3 code of the language of the message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+}
+
+impl TransactionFilter {
+    pub fn matches(&self, tx: &Transaction) -> bool {
+        if let Some(ref address) = self.address {
+            if !same_address(&tx.from, address) && !same_address(&tx.to, address) {
+                return false;
+            }
+        }
+
+        if let Some(ref from) = self.from {
+            if !same_address(&tx.from, from) {
+                return false;
+            }
+        }
+
+        if let Some(ref to) = self.to {
+            if !same_address(&tx.to, to) {
+                return false;
+            }
+        }
+
+        if self.since.map(|since| tx.timestamp < since).unwrap_or(false) {
+            return false;
+        }
+
+        if self.until.map(|until| tx.timestamp > until).unwrap_or(false) {
+            return false;
+        }
+
+        if let Some(ref text) = self.text {
+            if !tx.message.to_lowercase().contains(&text.to_lowercase()) {
+                return false;
+            }
+        }
+
+        if let Some(kind) = self.kind {
+            if tx.kind != Some(kind) {
+                return false;
+            }
+        }
+
+        if let Some(ref lang) = self.lang {
+            if !tx.lang.as_ref().map(|l| l.eq_ignore_ascii_case(lang)).unwrap_or(false) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub fn is_sent_by(tx: &Transaction, address: &str) -> bool {
+        same_address(&tx.from, address)
+    }
+
+    pub fn is_received_by(tx: &Transaction, address: &str) -> bool {
+        same_address(&tx.to, address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_matches() {
+        let transaction = Transaction {
+            hash: "0x1".to_string(),
+            message: "Hello World".to_string(),
+            timestamp: 100,
+            from: Some("0xAbC".to_string()),
+            to: Some("0xdef".to_string()),
+            kind: Some(Kind::Transfer),
+            lang: Some("eng".to_string()),
+            ..Default::default()
+        };
+
+        let filters = [
+            (TransactionFilter::default(), true),
+            (
+                TransactionFilter {
+                    address: Some("0xabc".to_string()),
+                    ..Default::default()
+                },
+                true,
+            ),
+            (
+                TransactionFilter {
+                    address: Some("0xdef".to_string()),
+                    ..Default::default()
+                },
+                true,
+            ),
+            (
+                TransactionFilter {
+                    from: Some("0xdef".to_string()),
+                    ..Default::default()
+                },
+                false,
+            ),
+            (
+                TransactionFilter {
+                    since: Some(100),
+                    until: Some(100),
+                    ..Default::default()
+                },
+                true,
+            ),
+            (
+                TransactionFilter {
+                    since: Some(101),
+                    ..Default::default()
+                },
+                false,
+            ),
+            (
+                TransactionFilter {
+                    text: Some("world".to_string()),
+                    ..Default::default()
+                },
+                true,
+            ),
+            (
+                TransactionFilter {
+                    text: Some("bye".to_string()),
+                    ..Default::default()
+                },
+                false,
+            ),
+            (
+                TransactionFilter {
+                    kind: Some(Kind::Transfer),
+                    ..Default::default()
+                },
+                true,
+            ),
+            (
+                TransactionFilter {
+                    kind: Some(Kind::ContractCreation),
+                    ..Default::default()
+                },
+                false,
+            ),
+            (
+                TransactionFilter {
+                    lang: Some("ENG".to_string()),
+                    ..Default::default()
+                },
+                true,
+            ),
+            (
+                TransactionFilter {
+                    lang: Some("rus".to_string()),
+                    ..Default::default()
+                },
+                false,
+            ),
+        ];
+
+        for (filter, expected) in filters.iter() {
+            assert_eq!(filter.matches(&transaction), *expected, "{:?}", filter);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use crate::api::v2::{self, Transaction};
+use crate::cache::TransactionCache;
+use crate::cursor::Cursor;
+use crate::filter::TransactionFilter;
+use crate::transaction;
+use async_graphql::futures_util::stream::{self, Stream, StreamExt};
+use async_graphql::{Context, Object, Schema, SimpleObject, Subscription};
+use async_graphql_warp::{GraphQLBadRequest, GraphQLResponse};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+const SECONDS_IN_DAY: u64 = 86400;
+const SUBSCRIPTION_POLL_INTERVAL: u64 = 1;
+
+pub type InterpretherSchema = Schema<QueryRoot, async_graphql::EmptyMutation, SubscriptionRoot>;
+
+fn now() -> u64 {
+    let start = SystemTime::now();
+    let since_the_epoch = start.duration_since(UNIX_EPOCH).expect("Time went backwards");
+    since_the_epoch.as_secs()
+}
+
+// Every query works on the last 24 hours of transactions, like the REST API, without
+// the messages hidden by moderation
+async fn last_day(cache: &TransactionCache) -> anyhow::Result<Vec<transaction::Transaction>> {
+    let max = now();
+    let min = max - SECONDS_IN_DAY;
+    cache.refresh(min).await?;
+
+    let mut transactions = cache.window(max, min).await;
+    transactions.retain(|tx| tx.moderation != Some(transaction::Moderation::Hidden));
+
+    Ok(transactions)
+}
+
+/// Aggregated figures over a set of transactions
+#[derive(SimpleObject, Default, Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub transaction_count: u64,
+    pub sender_count: u64,
+    pub recipient_count: u64,
+    pub contract_creation_count: u64,
+    pub failed_count: u64,
+    /// Timestamp of the oldest transaction
+    pub first_timestamp: Option<u64>,
+    /// Timestamp of the newest transaction
+    pub last_timestamp: Option<u64>,
+}
+
+impl Stats {
+    pub fn compute<'a, I: IntoIterator<Item = &'a transaction::Transaction>>(transactions: I) -> Self {
+        let mut stats = Stats::default();
+        let mut senders = HashSet::new();
+        let mut recipients = HashSet::new();
+
+        for tx in transactions {
+            stats.transaction_count += 1;
+
+            if let Some(ref from) = tx.from {
+                senders.insert(from.to_lowercase());
+            }
+
+            match tx.to {
+                Some(ref to) => {
+                    recipients.insert(to.to_lowercase());
+                }
+                None => stats.contract_creation_count += 1,
+            }
+
+            if tx.status == Some(false) {
+                stats.failed_count += 1;
+            }
+
+            stats.first_timestamp = Some(stats.first_timestamp.map_or(tx.timestamp, |t| t.min(tx.timestamp)));
+            stats.last_timestamp = Some(stats.last_timestamp.map_or(tx.timestamp, |t| t.max(tx.timestamp)));
+        }
+
+        stats.sender_count = senders.len() as u64;
+        stats.recipient_count = recipients.len() as u64;
+
+        stats
+    }
+}
+
+/// Transactions sent and received by an address
+pub struct Address {
+    address: String,
+    sent: Vec<transaction::Transaction>,
+    received: Vec<transaction::Transaction>,
+}
+
+#[Object]
+impl Address {
+    async fn address(&self) -> &str {
+        &self.address
+    }
+
+    async fn sent(&self) -> Vec<Transaction> {
+        self.sent.iter().map(Into::into).collect()
+    }
+
+    async fn received(&self) -> Vec<Transaction> {
+        self.received.iter().map(Into::into).collect()
+    }
+
+    async fn stats(&self) -> Stats {
+        let received = self
+            .received
+            .iter()
+            .filter(|tx| !TransactionFilter::is_sent_by(tx, &self.address));
+        Stats::compute(self.sent.iter().chain(received))
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Transactions of the last 24 hours matching `filter`, newest first
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<v2::TransactionFilter>,
+        limit: Option<usize>,
+    ) -> async_graphql::Result<Vec<Transaction>> {
+        let cache = ctx.data::<Arc<TransactionCache>>()?;
+        let filter = TransactionFilter::from(filter.unwrap_or_default());
+
+        Ok(last_day(cache)
+            .await?
+            .iter()
+            .filter(|tx| filter.matches(tx))
+            .take(limit.unwrap_or(usize::MAX))
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn transaction(&self, ctx: &Context<'_>, hash: String) -> async_graphql::Result<Option<Transaction>> {
+        let cache = ctx.data::<Arc<TransactionCache>>()?;
+
+        Ok(last_day(cache)
+            .await?
+            .iter()
+            .find(|tx| tx.hash.eq_ignore_ascii_case(&hash))
+            .map(Into::into))
+    }
+
+    async fn address(&self, ctx: &Context<'_>, addr: String) -> async_graphql::Result<Address> {
+        let cache = ctx.data::<Arc<TransactionCache>>()?;
+        let (sent, received) = last_day(cache)
+            .await?
+            .into_iter()
+            .filter(|tx| TransactionFilter::is_sent_by(tx, &addr) || TransactionFilter::is_received_by(tx, &addr))
+            .partition(|tx| TransactionFilter::is_sent_by(tx, &addr));
+
+        Ok(Address {
+            address: addr,
+            sent,
+            received,
+        })
+    }
+
+    async fn stats(&self, ctx: &Context<'_>, filter: Option<v2::TransactionFilter>) -> async_graphql::Result<Stats> {
+        let cache = ctx.data::<Arc<TransactionCache>>()?;
+        let filter = TransactionFilter::from(filter.unwrap_or_default());
+        let transactions = last_day(cache).await?;
+
+        Ok(Stats::compute(transactions.iter().filter(|tx| filter.matches(tx))))
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Transactions matching `filter` as soon as they are captured
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<v2::TransactionFilter>,
+    ) -> async_graphql::Result<impl Stream<Item = Transaction>> {
+        let cache = ctx.data::<Arc<TransactionCache>>()?.clone();
+        let filter = TransactionFilter::from(filter.unwrap_or_default());
+
+        // Only the transactions stored after the subscription started are sent
+        let max = now();
+        cache.refresh(max - SECONDS_IN_DAY).await?;
+        let mut cursor = Cursor::default();
+        if let Some(max_score) = cache.max_score().await {
+            cursor.advance(cache.window(max_score, max_score).await);
+        }
+
+        let stream = stream::unfold(cursor, move |mut cursor| {
+            let cache = cache.clone();
+            let filter = filter.clone();
+
+            async move {
+                loop {
+                    tokio::time::sleep(Duration::from_secs(SUBSCRIPTION_POLL_INTERVAL)).await;
+
+                    let max = now();
+                    if let Err(error) = cache.refresh(max - SECONDS_IN_DAY).await {
+                        log::error!("Error while refreshing txs for subscription: {:?}", error);
+                        continue;
+                    }
+
+                    let matching: Vec<Transaction> = cursor
+                        .advance(cache.window(u64::MAX, cursor.last_seen).await)
+                        .iter()
+                        .filter(|tx| filter.matches(tx))
+                        .map(Into::into)
+                        .collect();
+
+                    if !matching.is_empty() {
+                        return Some((stream::iter(matching), cursor));
+                    }
+                }
+            }
+        });
+
+        Ok(stream.flatten())
+    }
+}
+
+pub fn schema(cache: Arc<TransactionCache>) -> InterpretherSchema {
+    Schema::build(QueryRoot, async_graphql::EmptyMutation, SubscriptionRoot)
+        .data(cache)
+        .finish()
+}
+
+// Queries are served over POST /graphql, subscriptions over a websocket on the same path
+pub fn routes(cache: Arc<TransactionCache>) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let schema = schema(cache);
+
+    let subscription = warp::path("graphql")
+        .and(warp::path::end())
+        .and(async_graphql_warp::graphql_subscription(schema.clone()));
+
+    let query = warp::path("graphql")
+        .and(warp::path::end())
+        .and(async_graphql_warp::graphql(schema))
+        .and_then(
+            |(schema, request): (InterpretherSchema, async_graphql::Request)| async move {
+                Ok::<_, Infallible>(GraphQLResponse::from(schema.execute(request).await))
+            },
+        )
+        .recover(|err: Rejection| async move {
+            if let Some(GraphQLBadRequest(err)) = err.find() {
+                return Ok(warp::reply::with_status(err.to_string(), StatusCode::BAD_REQUEST));
+            }
+
+            Err(err)
+        });
+
+    subscription.or(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::Chain;
+
+    fn tx(hash: &str, from: &str, to: Option<&str>, timestamp: u64, message: &str) -> transaction::Transaction {
+        transaction::Transaction {
+            hash: hash.to_string(),
+            message: message.to_string(),
+            timestamp,
+            from: Some(from.to_string()),
+            to: to.map(|to| to.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let transactions = [
+            tx("0x1", "0xa", Some("0xb"), 300, "first"),
+            tx("0x2", "0xa", None, 100, "second"),
+            tx("0x3", "0xc", Some("0xb"), 200, "third"),
+        ];
+
+        assert_eq!(
+            Stats::compute(transactions.iter()),
+            Stats {
+                transaction_count: 3,
+                sender_count: 2,
+                recipient_count: 1,
+                contract_creation_count: 1,
+                failed_count: 0,
+                first_timestamp: Some(100),
+                last_timestamp: Some(300),
+            }
+        );
+    }
+
+    #[test]
+    fn test_schema_exposes_queries() {
+        let sdl = schema(Arc::new(TransactionCache::new(Chain::default()))).sdl();
+
+        for field in ["transactions(", "transaction(", "address(", "stats("] {
+            assert!(sdl.contains(field), "missing {}", field);
+        }
+        assert!(sdl.contains("type SubscriptionRoot"));
+    }
+}
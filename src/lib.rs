@@ -1,2 +1,20 @@
+pub mod abi;
+pub mod api;
+pub mod cache;
+pub mod chain;
+pub mod cleaner;
+pub mod codec;
+pub mod cursor;
+pub mod ens;
+pub mod extract;
+pub mod filter;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod links;
+pub mod moderation;
 pub mod redis;
+pub mod redis_pool;
+pub mod schema;
+pub mod spam;
 pub mod transaction;
+pub mod webhook;
@@ -0,0 +1,196 @@
+use crate::moderation::parse_list;
+use crate::transaction::{Link, LinkRisk};
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashSet;
+
+// URLs with a scheme, and bare domain names optionally followed by a path
+static LINK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\bhttps?://[^\s<>\x22'`()\[\]{}]+|\b(?:[\p{L}\p{N}](?:[\p{L}\p{N}-]{0,61}[\p{L}\p{N}])?\.)+(?:xn--[a-z0-9-]+|\p{L}{2,63})\b(?:/[^\s<>\x22'`()\[\]{}]*)?",
+    )
+    .expect("Invalid link pattern")
+});
+
+// Bare domain names are only taken for links when their TLD is one of these, or a risky one,
+// so that "e.g." or "notes.txt" are left alone
+const COMMON_TLDS: &[&str] = &[
+    "com", "org", "net", "io", "xyz", "app", "dev", "eth", "co", "me", "info", "finance", "fi", "gg", "so", "ai", "us",
+    "uk", "de", "fr", "ru", "cn", "jp", "site", "online", "link", "live", "network", "exchange", "money", "claims",
+];
+
+// Cheap or recently opened TLDs that drainer sites favour
+const RISKY_TLDS: &[&str] = &[
+    "zip", "mov", "top", "click", "icu", "cyou", "buzz", "lol", "quest", "sbs", "cfd", "bond", "rest", "shop", "pw",
+];
+
+// Characters ending a sentence rather than a link
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?'];
+
+// Finds the links of a message and tells the risky ones
+#[derive(Debug)]
+pub struct LinkChecker {
+    denylist: HashSet<String>,
+    risky_tlds: HashSet<String>,
+}
+
+impl Default for LinkChecker {
+    fn default() -> Self {
+        Self::new(vec![], RISKY_TLDS.iter().map(|tld| tld.to_string()).collect())
+    }
+}
+
+// Host of a link, lowercase and without credentials or port. Phishing links often look
+// like `https://uniswap.org@drainer.example`, whose host is the part after the `@`
+fn domain(link: &str) -> String {
+    let rest = match link.find("://") {
+        Some(index) => &link[index + 3..],
+        None => link,
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+
+    host.trim_end_matches('.').to_lowercase()
+}
+
+impl LinkChecker {
+    pub fn new(denylist: Vec<String>, risky_tlds: Vec<String>) -> Self {
+        Self {
+            denylist: denylist.into_iter().map(|domain| domain.to_lowercase()).collect(),
+            risky_tlds: risky_tlds
+                .into_iter()
+                .map(|tld| tld.trim().trim_start_matches('.').to_lowercase())
+                .filter(|tld| !tld.is_empty())
+                .collect(),
+        }
+    }
+
+    // Denied domains are read from the file at `LINK_DENYLIST`, one per line, and match their
+    // subdomains too. `LINK_RISKY_TLDS` replaces the default comma separated list of risky TLDs
+    pub fn from_env() -> Result<Self> {
+        let denylist = match std::env::var("LINK_DENYLIST") {
+            Ok(path) if !path.is_empty() => parse_list(
+                &std::fs::read_to_string(&path)
+                    .map_err(|error| anyhow::anyhow!("Could not read LINK_DENYLIST {}: {}", path, error))?,
+            ),
+            _ => vec![],
+        };
+
+        let risky_tlds = match std::env::var("LINK_RISKY_TLDS") {
+            Ok(tlds) => tlds.split(',').map(str::to_string).collect(),
+            Err(_) => RISKY_TLDS.iter().map(|tld| tld.to_string()).collect(),
+        };
+
+        Ok(Self::new(denylist, risky_tlds))
+    }
+
+    fn is_denied(&self, domain: &str) -> bool {
+        let mut rest = domain;
+        loop {
+            if self.denylist.contains(rest) {
+                return true;
+            }
+
+            match rest.split_once('.') {
+                Some((_, parent)) => rest = parent,
+                None => return false,
+            }
+        }
+    }
+
+    pub fn risk(&self, domain: &str) -> Option<LinkRisk> {
+        let tld = domain.rsplit('.').next().unwrap_or_default();
+
+        if self.is_denied(domain) {
+            Some(LinkRisk::Denylisted)
+        } else if !domain.is_ascii() || domain.split('.').any(|label| label.starts_with("xn--")) {
+            // Internationalized names can spell lookalikes of well known domains
+            Some(LinkRisk::Punycode)
+        } else if self.risky_tlds.contains(tld) {
+            Some(LinkRisk::FreshTld)
+        } else {
+            None
+        }
+    }
+
+    // Links of `message`, with their byte offsets in it
+    pub fn links(&self, message: &str) -> Vec<Link> {
+        LINK.find_iter(message)
+            .filter_map(|found| {
+                let text = found.as_str().trim_end_matches(TRAILING_PUNCTUATION);
+                let domain = domain(text);
+                let has_scheme = text.contains("://");
+                let tld = domain.rsplit('.').next().unwrap_or_default();
+
+                let is_link = has_scheme
+                    || COMMON_TLDS.contains(&tld)
+                    || self.risky_tlds.contains(tld)
+                    || tld.starts_with("xn--")
+                    || self.is_denied(&domain);
+                if domain.is_empty() || !is_link {
+                    return None;
+                }
+
+                Some(Link {
+                    start: found.start(),
+                    end: found.start() + text.len(),
+                    risk: self.risk(&domain),
+                    domain,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> LinkChecker {
+        LinkChecker::new(
+            vec!["drainer.example".to_string()],
+            RISKY_TLDS.iter().map(|tld| tld.to_string()).collect(),
+        )
+    }
+
+    fn found(message: &str) -> Vec<(&str, String, Option<LinkRisk>)> {
+        checker()
+            .links(message)
+            .into_iter()
+            .map(|link| (&message[link.start..link.end], link.domain, link.risk))
+            .collect()
+    }
+
+    #[test]
+    fn test_links() {
+        assert_eq!(
+            found("Visit https://uniswap.org/swap?x=1, or etherscan.io."),
+            vec![
+                ("https://uniswap.org/swap?x=1", "uniswap.org".to_string(), None),
+                ("etherscan.io", "etherscan.io".to_string(), None),
+            ]
+        );
+
+        // Not links
+        assert!(found("e.g. see notes.txt, version 1.2.3").is_empty());
+        assert!(found("gm").is_empty());
+    }
+
+    #[test]
+    fn test_risky_links() {
+        assert_eq!(
+            found("claim at https://app.drainer.example/claim")[0].2,
+            Some(LinkRisk::Denylisted)
+        );
+        assert_eq!(
+            found("https://uniswap.org@drainer.example")[0].1,
+            "drainer.example".to_string()
+        );
+        assert_eq!(found("go to xn--uniswp-0ta.org")[0].2, Some(LinkRisk::Punycode));
+        assert_eq!(found("go to https://unіswap.org")[0].2, Some(LinkRisk::Punycode));
+        assert_eq!(found("airdrop at uniswap-claim.top now")[0].2, Some(LinkRisk::FreshTld));
+        assert_eq!(found("https://example.com")[0].2, None);
+    }
+}
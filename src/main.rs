@@ -1,53 +1,295 @@
 use dotenv::dotenv;
+use interprether::api::{v1, v2};
+use interprether::cache::TransactionCache;
+use interprether::chain::Chain;
+use interprether::ens::NameService;
+use interprether::filter::TransactionFilter;
 use interprether::redis;
-use interprether::transaction::Transaction;
-use serde::Deserialize;
+use interprether::spam;
+use interprether::transaction::{Moderation, Source, Transaction};
+use interprether::webhook::{self, DeliveryStatus, NewSubscription, Subscription};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use warp::http::StatusCode;
 use warp::Filter;
 
 const SECONDS_IN_DAY: u64 = 86400;
+// Only the newest transactions of a response get ENS names, so that a request without a
+// limit doesn't look up the addresses of the whole window
+const MAX_NAMED_TRANSACTIONS: usize = 200;
 
 #[derive(Debug)]
 struct ServerError;
 
 impl warp::reject::Reject for ServerError {}
 
-// Query params for /transactions
-#[derive(Debug, Deserialize)]
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Interprether",
+        description = "Live feed of Ethereum transactions with text in their input data"
+    ),
+    paths(
+        get_transactions,
+        get_transactions_v2,
+        get_chains,
+        create_subscription,
+        get_subscription,
+        delete_subscription
+    ),
+    components(schemas(
+        v1::Transaction,
+        v2::Transaction,
+        v2::TransactionStatus,
+        v2::TransactionKind,
+        v2::MessageSource,
+        v2::MessageEncoding,
+        v2::MessageModeration,
+        v2::MessageLink,
+        v2::MessageLinkRisk,
+        v2::TransactionFilter,
+        Chain,
+        NewSubscription,
+        Subscription,
+        SubscriptionState,
+        DeliveryStatus,
+        ErrorResponse
+    ))
+)]
+struct ApiDoc;
+
+// Query params for /transactions and /v2/transactions
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct TransactionsQueryParams {
+    /// Only return transactions with a timestamp strictly greater than this one
     pub after: Option<u64>,
+    /// Maximum number of transactions to return
     pub limit: Option<usize>,
+    /// Name or id of the chain, defaults to the first one listed by `/chains`
+    pub chain: Option<String>,
+    /// Only return transactions of this kind
+    pub kind: Option<v2::TransactionKind>,
+    /// Only return messages in this language, as an ISO 639-3 code
+    pub lang: Option<String>,
+    /// Only return the newest transaction of every group of repeated messages, along with
+    /// the number of the others in `duplicates`
+    pub collapse: Option<bool>,
+}
+
+/// A registered webhook along with the outcome of its deliveries
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SubscriptionState {
+    pub subscription: Subscription,
+    pub status: DeliveryStatus,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+// Caches of the chains served by the API, the first one being the default
+#[derive(Clone)]
+struct ChainCaches(Arc<Vec<Arc<TransactionCache>>>);
+
+impl ChainCaches {
+    fn new(chains: Vec<Chain>) -> Self {
+        Self(Arc::new(
+            chains
+                .into_iter()
+                .map(|chain| Arc::new(TransactionCache::new(chain)))
+                .collect(),
+        ))
+    }
+
+    fn get(&self, chain: Option<&str>) -> Option<Arc<TransactionCache>> {
+        match chain {
+            None => self.0.first().cloned(),
+            Some(chain) => self
+                .0
+                .iter()
+                .find(|cache| {
+                    let chain = chain.trim();
+                    cache.chain().name.eq_ignore_ascii_case(chain) || cache.chain().id.to_string() == chain
+                })
+                .cloned(),
+        }
+    }
+
+    fn chains(&self) -> Vec<Chain> {
+        self.0.iter().map(|cache| cache.chain().clone()).collect()
+    }
+}
+
+fn unknown_chain(chain: Option<String>) -> warp::reply::WithStatus<warp::reply::Json> {
+    let body = ErrorResponse {
+        error: format!("Unknown chain {}", chain.unwrap_or_default()),
+    };
+
+    warp::reply::with_status(warp::reply::json(&body), StatusCode::BAD_REQUEST)
+}
+
+// Fills in the cached ENS names of senders and recipients of the newest transactions
+async fn add_names(transactions: &mut [v2::Transaction], names: &NameService) -> anyhow::Result<()> {
+    let count = std::cmp::min(transactions.len(), MAX_NAMED_TRANSACTIONS);
+    let transactions = &mut transactions[..count];
+
+    let addresses: HashSet<String> = transactions
+        .iter()
+        .flat_map(|tx| tx.from.iter().chain(tx.to.iter()))
+        .cloned()
+        .collect();
+
+    let names = names.names(addresses).await?;
+    for tx in transactions.iter_mut() {
+        tx.from_name = tx
+            .from
+            .as_ref()
+            .and_then(|from| names.get(&from.to_lowercase()))
+            .cloned();
+        tx.to_name = tx.to.as_ref().and_then(|to| names.get(&to.to_lowercase())).cloned();
+    }
+
+    Ok(())
 }
 
-async fn get_data(params: TransactionsQueryParams) -> anyhow::Result<Vec<Transaction>> {
+// Transactions of the window, each with the number of repeated messages collapsed into it.
+// Only messages found in `source` are returned when it is set
+async fn get_data(
+    params: TransactionsQueryParams,
+    cache: Arc<TransactionCache>,
+    source: Option<Source>,
+) -> anyhow::Result<Vec<(Transaction, u64)>> {
     let start = SystemTime::now();
     let since_the_epoch = start.duration_since(UNIX_EPOCH).expect("Time went backwards");
     let max = since_the_epoch.as_secs();
 
-    let mut min = max - SECONDS_IN_DAY;
+    let window_start = max - SECONDS_IN_DAY;
+    cache.refresh(window_start).await?;
+
+    let mut min = window_start;
     if let Some(a) = params.after {
         // Prevent clients from asking too much
         // data, or data that's too old
         min = std::cmp::max(min, a + 1);
     }
 
-    let result = redis::zrevrange_by_score(max, min).await?;
+    let mut transactions = cache.window(max, min).await;
+    // Messages hidden by moderation are never served
+    transactions.retain(|tx| tx.moderation != Some(Moderation::Hidden));
+    if let Some(source) = source {
+        transactions.retain(|tx| tx.source == source);
+    }
 
-    let mut transactions: Vec<Transaction> = vec![];
-    for item in result.iter() {
-        let parsed: Vec<Transaction> = serde_json::from_str(item)?;
-        transactions.extend(parsed);
+    let filter = TransactionFilter {
+        kind: params.kind.map(Into::into),
+        lang: params.lang,
+        ..Default::default()
+    };
+    if filter != TransactionFilter::default() {
+        transactions.retain(|tx| filter.matches(tx));
     }
 
-    match params.limit {
-        Some(l) => Ok(transactions[0..l].to_vec()),
-        None => Ok(transactions),
+    let mut transactions = if params.collapse.unwrap_or(false) {
+        spam::collapse(transactions)
+    } else {
+        transactions.into_iter().map(|tx| (tx, 0)).collect()
+    };
+
+    if let Some(l) = params.limit {
+        transactions.truncate(l);
+    }
+
+    Ok(transactions)
+}
+
+/// Transactions of the last 24 hours, newest first.
+///
+/// Kept for compatibility with existing clients, new ones should use `/v2/transactions`
+#[utoipa::path(
+    get,
+    path = "/transactions",
+    params(TransactionsQueryParams),
+    responses(
+        (status = 200, description = "Transactions of the last 24 hours", body = [v1::Transaction]),
+        (status = 400, description = "Unknown chain", body = ErrorResponse),
+        (status = 500, description = "Transactions could not be fetched")
+    )
+)]
+async fn get_transactions(
+    params: TransactionsQueryParams,
+    caches: ChainCaches,
+) -> anyhow::Result<impl warp::Reply, warp::Rejection> {
+    let cache = match caches.get(params.chain.as_deref()) {
+        Some(cache) => cache,
+        None => return Ok(unknown_chain(params.chain)),
+    };
+
+    // Messages found in event logs are only part of the v2 API
+    match get_data(params, cache, Some(Source::Input)).await {
+        Ok(transactions) => {
+            let transactions: Vec<v1::Transaction> = transactions.iter().map(|(tx, _)| tx.into()).collect();
+            Ok(warp::reply::with_status(
+                warp::reply::json(&transactions),
+                StatusCode::OK,
+            ))
+        }
+        Err(error) => {
+            log::error!("Error while fetching txs: {:?}", error);
+            Err(warp::reject::custom(ServerError))
+        }
     }
 }
 
-async fn get_transactions(params: TransactionsQueryParams) -> anyhow::Result<impl warp::Reply, warp::Rejection> {
-    match get_data(params).await {
-        Ok(transactions) => Ok(warp::reply::json(&transactions)),
+/// Transactions of the last 24 hours, newest first
+#[utoipa::path(
+    get,
+    path = "/v2/transactions",
+    params(TransactionsQueryParams),
+    responses(
+        (status = 200, description = "Transactions of the last 24 hours", body = [v2::Transaction]),
+        (status = 400, description = "Unknown chain", body = ErrorResponse),
+        (status = 500, description = "Transactions could not be fetched")
+    )
+)]
+async fn get_transactions_v2(
+    params: TransactionsQueryParams,
+    caches: ChainCaches,
+    names: Option<NameService>,
+) -> anyhow::Result<impl warp::Reply, warp::Rejection> {
+    let cache = match caches.get(params.chain.as_deref()) {
+        Some(cache) => cache,
+        None => return Ok(unknown_chain(params.chain)),
+    };
+
+    // ENS only names mainnet addresses
+    let names = names.filter(|_| cache.chain().id == 1);
+    let collapse = params.collapse.unwrap_or(false);
+
+    match get_data(params, cache, None).await {
+        Ok(transactions) => {
+            let mut transactions: Vec<v2::Transaction> = transactions
+                .iter()
+                .map(|(tx, duplicates)| v2::Transaction {
+                    duplicates: Some(*duplicates).filter(|_| collapse),
+                    ..tx.into()
+                })
+                .collect();
+            if let Some(names) = names {
+                if let Err(error) = add_names(&mut transactions, &names).await {
+                    log::warn!("Error while fetching names: {:?}", error);
+                }
+            }
+
+            Ok(warp::reply::with_status(
+                warp::reply::json(&transactions),
+                StatusCode::OK,
+            ))
+        }
         Err(error) => {
             log::error!("Error while fetching txs: {:?}", error);
             Err(warp::reject::custom(ServerError))
@@ -55,6 +297,140 @@ async fn get_transactions(params: TransactionsQueryParams) -> anyhow::Result<imp
     }
 }
 
+/// Chains whose transactions are served, the first one being the default
+#[utoipa::path(
+    get,
+    path = "/chains",
+    responses(
+        (status = 200, description = "Chains served by the API", body = [Chain])
+    )
+)]
+async fn get_chains(caches: ChainCaches) -> anyhow::Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&caches.chains()))
+}
+
+/// Registers a webhook receiving the transactions that match its filter.
+///
+/// Every delivery is a JSON POST signed with an HMAC-SHA256 of the body, keyed by the
+/// subscription secret and sent in the `X-Interprether-Signature` header
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    request_body = NewSubscription,
+    responses(
+        (status = 201, description = "Webhook registered, this is the only response containing its secret", body = Subscription),
+        (status = 400, description = "Invalid subscription", body = ErrorResponse),
+        (status = 500, description = "Webhook could not be registered")
+    )
+)]
+async fn create_subscription(request: NewSubscription) -> anyhow::Result<impl warp::Reply, warp::Rejection> {
+    let subscription = match Subscription::new(request) {
+        Ok(subscription) => subscription,
+        Err(error) => {
+            let body = ErrorResponse {
+                error: error.to_string(),
+            };
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&body),
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+
+    let allow_private_urls = match webhook::allow_private_urls() {
+        Ok(allow) => allow,
+        Err(error) => {
+            log::error!("Error while reading webhook settings: {:?}", error);
+            return Err(warp::reject::custom(ServerError));
+        }
+    };
+
+    // Webhooks can only be sent to public addresses
+    if let Err(error) = webhook::check_url(&subscription.url, allow_private_urls).await {
+        let body = ErrorResponse {
+            error: error.to_string(),
+        };
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&body),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let result = match serde_json::to_string(&subscription) {
+        Ok(serialized) => redis::save_subscription(&subscription.id, serialized).await,
+        Err(error) => Err(error.into()),
+    };
+
+    match result {
+        Ok(()) => Ok(warp::reply::with_status(
+            warp::reply::json(&subscription),
+            StatusCode::CREATED,
+        )),
+        Err(error) => {
+            log::error!("Error while saving subscription: {:?}", error);
+            Err(warp::reject::custom(ServerError))
+        }
+    }
+}
+
+async fn get_subscription_state(id: &str) -> anyhow::Result<Option<SubscriptionState>> {
+    let subscription: Subscription = match redis::get_subscription(id).await? {
+        Some(item) => serde_json::from_str(&item)?,
+        None => return Ok(None),
+    };
+
+    let status = DeliveryStatus::from_fields(redis::get_webhook_status(id).await?);
+
+    Ok(Some(SubscriptionState {
+        subscription: subscription.redacted(),
+        status,
+    }))
+}
+
+/// Webhook details and delivery status
+#[utoipa::path(
+    get,
+    path = "/subscriptions/{id}",
+    params(("id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 200, description = "Webhook found", body = SubscriptionState),
+        (status = 404, description = "Webhook not found"),
+        (status = 500, description = "Webhook could not be fetched")
+    )
+)]
+async fn get_subscription(id: String) -> anyhow::Result<impl warp::Reply, warp::Rejection> {
+    match get_subscription_state(&id).await {
+        Ok(Some(state)) => Ok(warp::reply::json(&state)),
+        Ok(None) => Err(warp::reject::not_found()),
+        Err(error) => {
+            log::error!("Error while fetching subscription: {:?}", error);
+            Err(warp::reject::custom(ServerError))
+        }
+    }
+}
+
+/// Unregisters a webhook, pending retries are dropped
+#[utoipa::path(
+    delete,
+    path = "/subscriptions/{id}",
+    params(("id" = String, Path, description = "Subscription id")),
+    responses(
+        (status = 204, description = "Webhook deleted"),
+        (status = 404, description = "Webhook not found"),
+        (status = 500, description = "Webhook could not be deleted")
+    )
+)]
+async fn delete_subscription(id: String) -> anyhow::Result<impl warp::Reply, warp::Rejection> {
+    match redis::delete_subscription(&id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(warp::reject::not_found()),
+        Err(error) => {
+            log::error!("Error while deleting subscription: {:?}", error);
+            Err(warp::reject::custom(ServerError))
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     dotenv().ok();
@@ -65,15 +441,232 @@ async fn main() {
     let log = warp::log("interprether");
 
     let origin = std::env::var("ORIGIN").expect("ORIGIN must be set");
-    let cors = warp::cors().allow_origin(origin.as_str());
+    // Subscriptions and GraphQL queries are sent as JSON, which browsers check beforehand
+    let cors = warp::cors()
+        .allow_origin(origin.as_str())
+        .allow_methods(["GET", "POST", "DELETE"])
+        .allow_header("content-type");
+
+    if let Err(error) = redis::connect().await {
+        log::error!("{}", error);
+        std::process::exit(1);
+    }
+
+    let chains = Chain::list_from_env().expect("CHAINS must be a comma separated list of chains");
+    let names = NameService::from_env().expect("ENS_PROVIDER_URL and ENS_CACHE_TTL must be valid");
+    if names.is_some() {
+        log::info!("Resolving ENS names");
+    }
+
+    let caches = ChainCaches::new(chains);
+    let with_caches = {
+        let caches = caches.clone();
+        warp::any().map(move || caches.clone())
+    };
 
     let transactions = warp::get()
         .and(warp::path("transactions"))
         .and(warp::path::end())
         .and(warp::query::<TransactionsQueryParams>())
-        .and_then(get_transactions)
-        .with(log)
-        .with(cors);
+        .and(with_caches.clone())
+        .and_then(get_transactions);
+
+    let transactions_v2 = warp::get()
+        .and(warp::path!("v2" / "transactions"))
+        .and(warp::query::<TransactionsQueryParams>())
+        .and(with_caches.clone())
+        .and(warp::any().map(move || names.clone()))
+        .and_then(get_transactions_v2);
+
+    let chains = warp::get()
+        .and(warp::path("chains"))
+        .and(warp::path::end())
+        .and(with_caches)
+        .and_then(get_chains);
+
+    let create_subscription = warp::post()
+        .and(warp::path("subscriptions"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::json())
+        .and_then(create_subscription);
+
+    let get_subscription = warp::get()
+        .and(warp::path!("subscriptions" / String))
+        .and_then(get_subscription);
+
+    let delete_subscription = warp::delete()
+        .and(warp::path!("subscriptions" / String))
+        .and_then(delete_subscription);
+
+    let openapi = warp::get()
+        .and(warp::path("openapi.json"))
+        .and(warp::path::end())
+        .map(|| warp::reply::json(&ApiDoc::openapi()));
+
+    let routes = transactions
+        .or(transactions_v2)
+        .or(chains)
+        .or(create_subscription)
+        .or(get_subscription)
+        .or(delete_subscription)
+        .or(openapi);
+
+    // GraphQL serves the default chain
+    #[cfg(feature = "graphql")]
+    let routes = routes.or(interprether::graphql::routes(
+        caches.get(None).expect("At least one chain is served"),
+    ));
 
-    warp::serve(transactions).run(([0, 0, 0, 0], 3030)).await;
+    warp::serve(routes.with(log).with(cors)).run(([0, 0, 0, 0], 3030)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interprether::transaction::{Encoding, Kind};
+
+    // Validates `value` against the schema published for `component`
+    fn validate(value: &serde_json::Value, component: &str) -> bool {
+        let mut document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        document["$ref"] = serde_json::Value::String(format!("#/components/schemas/{}", component));
+
+        let validator = jsonschema::validator_for(&document).unwrap();
+        validator.is_valid(value)
+    }
+
+    fn transactions() -> Vec<Transaction> {
+        vec![
+            Transaction {
+                hash: "0x1".to_string(),
+                message: "hello!".to_string(),
+                timestamp: 1630000000,
+                from: Some("0x2".to_string()),
+                to: Some("0x3".to_string()),
+                kind: Some(Kind::Transfer),
+                block_number: Some(13000000),
+                transaction_index: Some(4),
+                value: Some("1000000000000000000".to_string()),
+                status: Some(true),
+                gas_used: Some(21432),
+                gas_price: Some("50000000000".to_string()),
+                nonce: Some(7),
+                confidence: Some(1.0),
+                argument: None,
+                encoding: Encoding::Hex,
+                media_type: None,
+                payload_size: None,
+                lang: Some("eng".to_string()),
+                duplicate_of: None,
+                spam_score: Some(0.0),
+                moderation: None,
+                links: vec![],
+                source: Source::Input,
+                log_index: None,
+            },
+            Transaction {
+                hash: "0x4".to_string(),
+                message: "contract".to_string(),
+                timestamp: 1630000000,
+                from: Some("0x5".to_string()),
+                to: None,
+                kind: Some(Kind::ContractCreation),
+                status: Some(false),
+                ..Default::default()
+            },
+            Transaction {
+                hash: "0x9".to_string(),
+                message: "memo".to_string(),
+                timestamp: 1630000000,
+                from: Some("0xa".to_string()),
+                to: Some("0xb".to_string()),
+                status: Some(true),
+                argument: Some("0x12345678[1]".to_string()),
+                source: Source::Log,
+                log_index: Some(12),
+                ..Default::default()
+            },
+            // Stored before block data was captured
+            Transaction {
+                hash: "0x6".to_string(),
+                message: "old".to_string(),
+                timestamp: 1630000000,
+                from: Some("0x7".to_string()),
+                to: Some("0x8".to_string()),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_v1_transaction_matches_schema() {
+        for tx in transactions().iter() {
+            let value = serde_json::to_value::<v1::Transaction>(tx.into()).unwrap();
+            assert!(validate(&value, "v1.Transaction"));
+        }
+    }
+
+    #[test]
+    fn test_v2_transaction_matches_schema() {
+        for tx in transactions().iter() {
+            let value = serde_json::to_value::<v2::Transaction>(tx.into()).unwrap();
+            assert!(validate(&value, "v2.Transaction"));
+        }
+    }
+
+    #[test]
+    fn test_v1_schema_rejects_long_field_names() {
+        let value = serde_json::json!({ "hash": "0x1", "message": "hello!", "timestamp": 1630000000 });
+        assert!(!validate(&value, "v1.Transaction"));
+    }
+
+    #[test]
+    fn test_v2_schema_rejects_unknown_status() {
+        let mut value = serde_json::to_value::<v2::Transaction>((&transactions()[0]).into()).unwrap();
+        value["status"] = serde_json::Value::String("pending".to_string());
+        assert!(!validate(&value, "v2.Transaction"));
+    }
+
+    #[test]
+    fn test_v2_omits_unknown_sender() {
+        let tx = Transaction {
+            from: None,
+            ..transactions()[0].clone()
+        };
+        let value = serde_json::to_value::<v2::Transaction>((&tx).into()).unwrap();
+        assert!(value.get("from").is_none());
+        assert!(validate(&value, "v2.Transaction"));
+    }
+
+    #[test]
+    fn test_v1_keeps_legacy_wire_format() {
+        let value = serde_json::to_value::<v1::Transaction>((&transactions()[0]).into()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "h": "0x1", "m": "hello!", "t": 1630000000, "from": "0x2", "to": "0x3" })
+        );
+    }
+
+    #[test]
+    fn test_openapi_documents_transactions_routes() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+        for path in ["/transactions", "/v2/transactions"] {
+            let parameters = document["paths"][path]["get"]["parameters"].as_array().unwrap();
+
+            let names: Vec<&str> = parameters.iter().map(|p| p["name"].as_str().unwrap()).collect();
+            assert_eq!(names, vec!["after", "limit", "chain", "kind", "lang", "collapse"]);
+        }
+    }
+
+    #[test]
+    fn test_chain_caches() {
+        let caches = ChainCaches::new(vec![Chain::default(), Chain::parse("base").unwrap()]);
+
+        assert_eq!(caches.get(None).unwrap().chain().name, "mainnet");
+        assert_eq!(caches.get(Some("base")).unwrap().chain().name, "base");
+        assert_eq!(caches.get(Some("8453")).unwrap().chain().name, "base");
+        assert_eq!(caches.get(Some("Mainnet")).unwrap().chain().name, "mainnet");
+        assert!(caches.get(Some("optimism")).is_none());
+    }
 }
@@ -0,0 +1,215 @@
+use crate::chain::Chain;
+use crate::redis;
+use crate::transaction::{Moderation, Transaction};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::str::FromStr;
+use web3::ethabi::ethereum_types::H256;
+
+// Words and regexes matched against messages. Hidden messages are left out of the API,
+// flagged ones are blurred by the frontend until clicked
+#[derive(Debug, Default)]
+pub struct Moderator {
+    hide: Option<regex::Regex>,
+    flag: Option<regex::Regex>,
+}
+
+// Entries of a list file, one per line. Blank lines and lines starting with `#` are skipped
+pub fn parse_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// An entry only matches whole words. A word boundary next to a character that is not part
+// of a word would keep the entry from ever matching, e.g. `$scam`, so there is none there
+fn literal(entry: &str) -> String {
+    let start = if entry.starts_with(is_word_char) { r"\b" } else { "" };
+    let end = if entry.ends_with(is_word_char) { r"\b" } else { "" };
+
+    format!("{}{}{}", start, regex::escape(entry), end)
+}
+
+// A single case insensitive regex matching any entry. Entries written as `/.../` are regexes,
+// the other ones are words or phrases that only match whole words
+fn compile(entries: &[String]) -> Result<Option<regex::Regex>> {
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    let alternatives: Vec<String> = entries
+        .iter()
+        .map(
+            |entry| match entry.strip_prefix('/').and_then(|e| e.strip_suffix('/')) {
+                Some(pattern) if !pattern.is_empty() => format!("(?:{})", pattern),
+                _ => literal(entry),
+            },
+        )
+        .collect();
+
+    Ok(Some(regex::Regex::new(&format!("(?i){}", alternatives.join("|")))?))
+}
+
+fn read_list(variable: &str) -> Result<Vec<String>> {
+    match std::env::var(variable) {
+        Ok(path) if !path.is_empty() => {
+            let text = std::fs::read_to_string(&path)
+                .map_err(|error| anyhow::anyhow!("Could not read {} {}: {}", variable, path, error))?;
+            Ok(parse_list(&text))
+        }
+        _ => Ok(vec![]),
+    }
+}
+
+impl Moderator {
+    pub fn new(hide: &[String], flag: &[String]) -> Result<Self> {
+        Ok(Self {
+            hide: compile(hide)?,
+            flag: compile(flag)?,
+        })
+    }
+
+    // Lists are read from the files at `MODERATION_HIDE_LIST` and `MODERATION_FLAG_LIST`
+    pub fn from_env() -> Result<Self> {
+        Self::new(&read_list("MODERATION_HIDE_LIST")?, &read_list("MODERATION_FLAG_LIST")?)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.hide.is_some() || self.flag.is_some()
+    }
+
+    pub fn moderate(&self, message: &str) -> Option<Moderation> {
+        if self.hide.as_ref().map(|r| r.is_match(message)).unwrap_or(false) {
+            Some(Moderation::Hidden)
+        } else if self.flag.as_ref().map(|r| r.is_match(message)).unwrap_or(false) {
+            Some(Moderation::Flagged)
+        } else {
+            None
+        }
+    }
+}
+
+// Decision of an admin about a transaction, taking precedence over the lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Override {
+    Visible,
+    Flagged,
+    Hidden,
+}
+
+impl FromStr for Override {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "visible" => Ok(Override::Visible),
+            "flagged" => Ok(Override::Flagged),
+            "hidden" => Ok(Override::Hidden),
+            _ => Err(anyhow::anyhow!(
+                "Unknown moderation {}, expected visible, flagged or hidden",
+                value
+            )),
+        }
+    }
+}
+
+impl Override {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Override::Visible => "visible",
+            Override::Flagged => "flagged",
+            Override::Hidden => "hidden",
+        }
+    }
+
+    pub fn apply(&self, tx: &mut Transaction) {
+        tx.moderation = match self {
+            Override::Visible => None,
+            Override::Flagged => Some(Moderation::Flagged),
+            Override::Hidden => Some(Moderation::Hidden),
+        };
+    }
+}
+
+// Decisions of admins about the transactions of `chain`, by transaction hash. Invalid ones
+// are skipped
+pub async fn load_overrides(chain: &Chain) -> Result<HashMap<String, Override>> {
+    let mut overrides = HashMap::new();
+    for (hash, value) in redis::get_moderation_overrides(chain).await? {
+        match value.parse::<Override>() {
+            Ok(decision) => {
+                overrides.insert(hash, decision);
+            }
+            Err(error) => log::warn!("Ignoring moderation override of {}: {}", hash, error),
+        }
+    }
+
+    Ok(overrides)
+}
+
+// Transaction hashes are stored in lowercase, as the scanner formats them
+pub fn parse_hash(hash: &str) -> Result<String> {
+    let parsed = H256::from_str(hash.trim_start_matches("0x"))?;
+    Ok(format!("{:?}", parsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moderator() -> Moderator {
+        let hide = parse_list("# Slurs\nbadword\n\n/free\\s+eth\\s+at\\s+\\S+/\n");
+        let flag = parse_list("airdrop\nclaim now\n$scam\nfree mint!\n@handle");
+        Moderator::new(&hide, &flag).unwrap()
+    }
+
+    #[test]
+    fn test_moderate() {
+        let moderator = moderator();
+        assert!(moderator.is_enabled());
+
+        assert_eq!(moderator.moderate("what a BADWORD"), Some(Moderation::Hidden));
+        assert_eq!(moderator.moderate("Free ETH at scam.example"), Some(Moderation::Hidden));
+        assert_eq!(moderator.moderate("Your airdrop is ready"), Some(Moderation::Flagged));
+        assert_eq!(moderator.moderate("Claim now!"), Some(Moderation::Flagged));
+        // Entries starting or ending with other characters than letters and digits
+        assert_eq!(moderator.moderate("buy $SCAM today"), Some(Moderation::Flagged));
+        assert_eq!(moderator.moderate("Free mint! Hurry"), Some(Moderation::Flagged));
+        assert_eq!(moderator.moderate("follow @handle"), Some(Moderation::Flagged));
+        assert_eq!(moderator.moderate("$scammer"), None);
+        assert_eq!(moderator.moderate("free minting"), None);
+        // Hiding wins over flagging
+        assert_eq!(moderator.moderate("airdrop badword"), Some(Moderation::Hidden));
+
+        // Words only match whole
+        assert_eq!(moderator.moderate("badwords are not listed"), None);
+        assert_eq!(moderator.moderate("gm"), None);
+
+        assert!(!Moderator::default().is_enabled());
+        assert_eq!(Moderator::default().moderate("badword"), None);
+    }
+
+    #[test]
+    fn test_override() {
+        let mut tx = Transaction {
+            moderation: Some(Moderation::Hidden),
+            ..Default::default()
+        };
+
+        "visible".parse::<Override>().unwrap().apply(&mut tx);
+        assert_eq!(tx.moderation, None);
+        Override::Flagged.apply(&mut tx);
+        assert_eq!(tx.moderation, Some(Moderation::Flagged));
+        assert!("shown".parse::<Override>().is_err());
+
+        let hash = format!("0x{}", "AB".repeat(32));
+        assert_eq!(parse_hash(&hash).unwrap(), format!("0x{}", "ab".repeat(32)));
+        assert!(parse_hash("0x1234").is_err());
+    }
+}
@@ -1,48 +1,697 @@
+use crate::chain::Chain;
+use crate::redis_pool::{Pool, RedisConfig, CHAIN_PREFIX};
 use anyhow::Result;
-use deadpool_redis::redis::cmd;
-use deadpool_redis::{Config, Pool};
-use once_cell::sync::Lazy;
-use std::sync::Arc;
+use deadpool_redis::redis::{cmd, Script};
+use once_cell::sync::{Lazy, OnceCell};
 
 const TX_SORTED_SET: &str = "tx_set";
+const TX_SET_GENERATION: &str = "tx_set:generation";
+const TX_INDEX: &str = "tx_index";
+const MODERATION_OVERRIDES: &str = "moderation:overrides";
 
-static POOL: Lazy<Arc<Pool>> = Lazy::new(|| {
-    let redis_url = std::env::var("REDIS_URL").expect("REDIS_URL must be set");
-    let cfg = Config::from_url(redis_url);
-    let pool = cfg.create_pool().unwrap();
+static CONFIG: OnceCell<RedisConfig> = OnceCell::new();
+static POOL: OnceCell<Pool> = OnceCell::new();
 
-    Arc::new(pool)
+fn config() -> Result<&'static RedisConfig> {
+    CONFIG.get_or_try_init(RedisConfig::from_env)
+}
+
+fn pool() -> Result<&'static Pool> {
+    POOL.get_or_try_init(|| config()?.create_pool())
+}
+
+// Checks the configuration and that Redis answers, so that processes stop at startup
+// with a clear error rather than on their first command
+pub async fn connect() -> Result<()> {
+    let mut conn = pool()?
+        .get()
+        .await
+        .map_err(|error| anyhow::anyhow!("Could not connect to Redis: {}", error))?;
+
+    cmd("PING")
+        .query_async::<()>(&mut conn)
+        .await
+        .map_err(|error| anyhow::anyhow!("Redis did not answer: {}", error))?;
+
+    if let Some(namespace) = namespace() {
+        log::info!("Using the {} Redis namespace", namespace);
+    }
+
+    Ok(())
+}
+
+// Namespace of the deployment, prefixing every key so that several deployments can share a Redis
+pub fn namespace() -> Option<&'static str> {
+    config().ok().and_then(|config| config.namespace.as_deref())
+}
+
+// Fake transactions only go to the keys without namespace, usually the production ones, when
+// `force` is set
+pub fn check_fake_data_namespace(force: bool) -> Result<()> {
+    match namespace() {
+        Some(namespace) => {
+            log::info!("Writing fake data to the {} namespace", namespace);
+            Ok(())
+        }
+        None if force => {
+            log::warn!("Writing fake data to keys without namespace");
+            Ok(())
+        }
+        None => Err(anyhow::anyhow!(
+            "REDIS_NAMESPACE is not set, pass --force to write fake data to keys without namespace"
+        )),
+    }
+}
+
+// Every key goes through here, to get the namespace. An invalid configuration fails on the
+// connection that follows
+fn key(name: &str) -> String {
+    match config() {
+        Ok(config) => config.key(name),
+        Err(_) => name.to_string(),
+    }
+}
+
+// Every chain has its own keyspace, e.g. `chain:mainnet:tx_set`
+fn chain_key(chain: &Chain, name: &str) -> String {
+    match config() {
+        Ok(config) => config.chain_key(&chain.name, name),
+        Err(_) => format!("{}:{}:{}", CHAIN_PREFIX, chain.name, name),
+    }
+}
+
+// The sorted set holds transaction ids scored by timestamp, and the index maps every id to
+// the stored transaction. Writing a transaction again is a no-op, writing it with another
+// content or timestamp replaces it and bumps the generation so that readers reload the set
+static ADD_TRANSACTIONS: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+        local changed = 0
+        for i = 1, #ARGV, 3 do
+            local id, score, value = ARGV[i], ARGV[i + 1], ARGV[i + 2]
+            local previous = redis.call('HGET', KEYS[2], id)
+            local previous_score = redis.call('ZSCORE', KEYS[1], id)
+            if previous and (previous ~= value or tonumber(previous_score) ~= tonumber(score)) then
+                changed = changed + 1
+            end
+            redis.call('HSET', KEYS[2], id, value)
+            redis.call('ZADD', KEYS[1], score, id)
+        end
+        if changed > 0 then
+            redis.call('INCR', KEYS[3])
+        end
+        return changed
+        ",
+    )
+});
+
+// Stored transactions with `min <= score <= max`, along with their score, in the order of
+// the sorted set. Members written before the index existed hold a JSON array of transactions
+// and are returned as they are
+static RANGE_BY_SCORE: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+        local ids
+        if ARGV[3] == 'rev' then
+            ids = redis.call('ZREVRANGEBYSCORE', KEYS[1], ARGV[2], ARGV[1], 'WITHSCORES')
+        else
+            ids = redis.call('ZRANGEBYSCORE', KEYS[1], ARGV[1], ARGV[2], 'WITHSCORES')
+        end
+        local result = {}
+        for i = 1, #ids, 2 do
+            local value = redis.call('HGET', KEYS[2], ids[i])
+            if not value and string.sub(ids[i], 1, 1) == '[' then
+                value = ids[i]
+            end
+            if value then
+                table.insert(result, value)
+                table.insert(result, ids[i + 1])
+            end
+        end
+        return result
+        ",
+    )
+});
+
+static REMOVE_RANGE_BY_SCORE: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+        local ids = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+        for i = 1, #ids, 1000 do
+            redis.call('HDEL', KEYS[2], unpack(ids, i, math.min(i + 999, #ids)))
+        end
+        return redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+        ",
+    )
+});
+
+// Members of the sorted set with a score lower or equal than `max`, each along with the
+// transaction it stands for
+static MEMBERS_UP_TO: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+        local ids = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+        local result = {}
+        for i = 1, #ids do
+            local value = redis.call('HGET', KEYS[2], ids[i])
+            if not value and string.sub(ids[i], 1, 1) == '[' then
+                value = ids[i]
+            end
+            if value then
+                table.insert(result, ids[i])
+                table.insert(result, value)
+            end
+        end
+        return result
+        ",
+    )
+});
+
+// Removes `(member, value)` pairs from the set and the index, unless the transaction was
+// written again with another content in the meantime
+static REMOVE_MEMBERS: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+        local removed = 0
+        for i = 1, #ARGV, 2 do
+            local id, value = ARGV[i], ARGV[i + 1]
+            local current = redis.call('HGET', KEYS[2], id)
+            if current == value or (not current and id == value) then
+                removed = removed + redis.call('ZREM', KEYS[1], id)
+                redis.call('HDEL', KEYS[2], id)
+            end
+        end
+        return removed
+        ",
+    )
+});
+
+// Rewrites transactions that are still stored as they were read, so that a rewrite doesn't
+// bring back transactions removed in the meantime nor undo newer writes
+static REPLACE_TRANSACTIONS: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+        local replaced = 0
+        for i = 1, #ARGV, 3 do
+            local id, previous, value = ARGV[i], ARGV[i + 1], ARGV[i + 2]
+            if redis.call('HGET', KEYS[2], id) == previous and redis.call('ZSCORE', KEYS[1], id) then
+                redis.call('HSET', KEYS[2], id, value)
+                replaced = replaced + 1
+            end
+        end
+        if replaced > 0 then
+            redis.call('INCR', KEYS[3])
+        end
+        return replaced
+        ",
+    )
 });
 
-pub async fn zadd(score: u64, value: String) -> Result<()> {
-    let mut conn = POOL.get().await?;
+// Writes `(id, score, value)` entries, returning the number of transactions that were replaced
+pub async fn add_transactions(chain: &Chain, entries: Vec<(String, u64, Vec<u8>)>) -> Result<u64> {
+    if entries.is_empty() {
+        return Ok(0);
+    }
+
+    let mut conn = pool()?.get().await?;
+
+    let mut invocation = ADD_TRANSACTIONS.prepare_invoke();
+    invocation
+        .key(chain_key(chain, TX_SORTED_SET))
+        .key(chain_key(chain, TX_INDEX))
+        .key(chain_key(chain, TX_SET_GENERATION));
+    for (id, score, value) in entries {
+        invocation.arg(id).arg(score).arg(value);
+    }
+
+    let changed: u64 = invocation.invoke_async(&mut conn).await?;
+
+    Ok(changed)
+}
+
+// Replaces `(id, previous value, value)` entries whose stored value is still the previous
+// one, returning the number of transactions replaced
+pub async fn replace_transactions(chain: &Chain, entries: Vec<(String, Vec<u8>, Vec<u8>)>) -> Result<u64> {
+    if entries.is_empty() {
+        return Ok(0);
+    }
 
-    let _ = cmd("ZADD")
-        .arg(&[TX_SORTED_SET.to_string(), score.to_string(), value])
-        .query_async::<_, ()>(&mut conn)
+    let mut conn = pool()?.get().await?;
+
+    let mut invocation = REPLACE_TRANSACTIONS.prepare_invoke();
+    invocation
+        .key(chain_key(chain, TX_SORTED_SET))
+        .key(chain_key(chain, TX_INDEX))
+        .key(chain_key(chain, TX_SET_GENERATION));
+    for (id, previous, value) in entries {
+        invocation.arg(id).arg(previous).arg(value);
+    }
+
+    let replaced: u64 = invocation.invoke_async(&mut conn).await?;
+
+    Ok(replaced)
+}
+
+async fn range_by_score(chain: &Chain, min: &str, max: &str, order: &str) -> Result<Vec<(Vec<u8>, u64)>> {
+    let mut conn = pool()?.get().await?;
+
+    let value: Vec<(Vec<u8>, u64)> = RANGE_BY_SCORE
+        .key(chain_key(chain, TX_SORTED_SET))
+        .key(chain_key(chain, TX_INDEX))
+        .arg(min)
+        .arg(max)
+        .arg(order)
+        .invoke_async(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+pub async fn zrevrange_by_score(chain: &Chain, max: u64, min: u64) -> Result<Vec<Vec<u8>>> {
+    let value = range_by_score(chain, &min.to_string(), &max.to_string(), "rev").await?;
+
+    Ok(value.into_iter().map(|(member, _)| member).collect())
+}
+
+// Returns every stored transaction with a score greater or equal than `min`, along with its score
+pub async fn zrange_by_score_with_scores(chain: &Chain, min: u64) -> Result<Vec<(Vec<u8>, u64)>> {
+    range_by_score(chain, &min.to_string(), "+inf", "").await
+}
+
+// Returns the `(member, value)` pairs of every stored transaction with a score lower or
+// equal than `max`, oldest first
+pub async fn members_up_to(chain: &Chain, max: u64) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut conn = pool()?.get().await?;
+
+    let value: Vec<(Vec<u8>, Vec<u8>)> = MEMBERS_UP_TO
+        .key(chain_key(chain, TX_SORTED_SET))
+        .key(chain_key(chain, TX_INDEX))
+        .arg(max)
+        .invoke_async(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+// Removes the `(member, value)` pairs returned by `members_up_to`, returns the number of
+// members removed
+pub async fn remove_members(chain: &Chain, members: &[(Vec<u8>, Vec<u8>)]) -> Result<u64> {
+    if members.is_empty() {
+        return Ok(0);
+    }
+
+    let mut conn = pool()?.get().await?;
+
+    let mut invocation = REMOVE_MEMBERS.prepare_invoke();
+    invocation
+        .key(chain_key(chain, TX_SORTED_SET))
+        .key(chain_key(chain, TX_INDEX));
+    for (member, value) in members {
+        invocation.arg(member.as_slice()).arg(value.as_slice());
+    }
+
+    let removed: u64 = invocation.invoke_async(&mut conn).await?;
+
+    Ok(removed)
+}
+
+// Removes the transactions with a score lower or equal than `max` from the set and the index
+pub async fn zremrange_by_score(chain: &Chain, max: u64) -> Result<u64> {
+    let mut conn = pool()?.get().await?;
+
+    let value: u64 = REMOVE_RANGE_BY_SCORE
+        .key(chain_key(chain, TX_SORTED_SET))
+        .key(chain_key(chain, TX_INDEX))
+        .arg(max)
+        .invoke_async(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+// Every stored transaction, by id
+pub async fn get_transaction_records(chain: &Chain) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut conn = pool()?.get().await?;
+
+    let value: Vec<(String, Vec<u8>)> = cmd("HGETALL")
+        .arg(chain_key(chain, TX_INDEX))
+        .query_async::<Vec<(String, Vec<u8>)>>(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+// Members written before the index existed, each one a JSON array of transactions
+pub async fn legacy_members(chain: &Chain) -> Result<Vec<(String, u64)>> {
+    let mut conn = pool()?.get().await?;
+
+    let members: Vec<(String, u64)> = cmd("ZRANGEBYSCORE")
+        .arg(&[
+            chain_key(chain, TX_SORTED_SET),
+            "-inf".to_string(),
+            "+inf".to_string(),
+            "WITHSCORES".to_string(),
+        ])
+        .query_async::<Vec<(String, u64)>>(&mut conn)
+        .await?;
+
+    Ok(members
+        .into_iter()
+        .filter(|(member, _)| member.starts_with('['))
+        .collect())
+}
+
+pub async fn zrem(chain: &Chain, member: String) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("ZREM")
+        .arg(&[chain_key(chain, TX_SORTED_SET), member])
+        .query_async::<()>(&mut conn)
         .await?;
 
     Ok(())
 }
 
-pub async fn zrevrange_by_score(max: u64, min: u64) -> Result<Vec<String>> {
-    let mut conn = POOL.get().await?;
+// The generation counter is bumped every time members are removed from the set,
+// so that readers holding a copy of it know when to throw it away
+pub async fn get_generation(chain: &Chain) -> Result<u64> {
+    let mut conn = pool()?.get().await?;
 
-    let value: Vec<String> = cmd("ZREVRANGEBYSCORE")
-        .arg(&[TX_SORTED_SET.to_string(), max.to_string(), min.to_string()])
-        .query_async::<_, Vec<String>>(&mut conn)
+    let value: Option<u64> = cmd("GET")
+        .arg(chain_key(chain, TX_SET_GENERATION))
+        .query_async::<Option<u64>>(&mut conn)
+        .await?;
+
+    Ok(value.unwrap_or(0))
+}
+
+pub async fn incr_generation(chain: &Chain) -> Result<u64> {
+    let mut conn = pool()?.get().await?;
+
+    let value: u64 = cmd("INCR")
+        .arg(chain_key(chain, TX_SET_GENERATION))
+        .query_async::<u64>(&mut conn)
         .await?;
 
     Ok(value)
 }
 
-pub async fn zremrange_by_score(max: u64) -> Result<u64> {
-    let mut conn = POOL.get().await?;
+// Moderation decisions of admins, by transaction hash
+pub async fn get_moderation_overrides(chain: &Chain) -> Result<Vec<(String, String)>> {
+    let mut conn = pool()?.get().await?;
 
-    let value: u64 = cmd("ZREMRANGEBYSCORE")
-        .arg(&[TX_SORTED_SET.to_string(), "-inf".to_string(), max.to_string()])
-        .query_async::<_, u64>(&mut conn)
+    let value: Vec<(String, String)> = cmd("HGETALL")
+        .arg(chain_key(chain, MODERATION_OVERRIDES))
+        .query_async::<Vec<(String, String)>>(&mut conn)
         .await?;
 
     Ok(value)
 }
+
+pub async fn set_moderation_override(chain: &Chain, hash: &str, value: &str) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("HSET")
+        .arg(&[
+            chain_key(chain, MODERATION_OVERRIDES),
+            hash.to_string(),
+            value.to_string(),
+        ])
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+// Returns whether there was an override to delete
+pub async fn delete_moderation_override(chain: &Chain, hash: &str) -> Result<bool> {
+    let mut conn = pool()?.get().await?;
+
+    let deleted: u64 = cmd("HDEL")
+        .arg(&[chain_key(chain, MODERATION_OVERRIDES), hash.to_string()])
+        .query_async::<u64>(&mut conn)
+        .await?;
+
+    Ok(deleted > 0)
+}
+
+const CLEANER_LOCK: &str = "cleaner:lock";
+const CLEANER_STATUS: &str = "cleaner:status";
+
+// Deletes the lock only when it is still held by the caller, it may have expired and
+// been taken by another instance in the meantime
+static RELEASE_LOCK: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r"
+        if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('DEL', KEYS[1])
+        end
+        return 0
+        ",
+    )
+});
+
+// Takes the cleaning lock of `chain` for `ttl` seconds, returns whether it was free
+pub async fn acquire_cleaner_lock(chain: &Chain, token: &str, ttl: u64) -> Result<bool> {
+    let mut conn = pool()?.get().await?;
+
+    let value: Option<String> = cmd("SET")
+        .arg(&[
+            chain_key(chain, CLEANER_LOCK),
+            token.to_string(),
+            "NX".to_string(),
+            "EX".to_string(),
+            ttl.to_string(),
+        ])
+        .query_async::<Option<String>>(&mut conn)
+        .await?;
+
+    Ok(value.is_some())
+}
+
+pub async fn release_cleaner_lock(chain: &Chain, token: &str) -> Result<bool> {
+    let mut conn = pool()?.get().await?;
+
+    let released: u64 = RELEASE_LOCK
+        .key(chain_key(chain, CLEANER_LOCK))
+        .arg(token)
+        .invoke_async(&mut conn)
+        .await?;
+
+    Ok(released > 0)
+}
+
+pub async fn incr_cleaner_status(chain: &Chain, field: &str, value: u64) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("HINCRBY")
+        .arg(&[chain_key(chain, CLEANER_STATUS), field.to_string(), value.to_string()])
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn set_cleaner_status(chain: &Chain, field: &str, value: String) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("HSET")
+        .arg(&[chain_key(chain, CLEANER_STATUS), field.to_string(), value])
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_cleaner_status(chain: &Chain) -> Result<Vec<(String, String)>> {
+    let mut conn = pool()?.get().await?;
+
+    let value: Vec<(String, String)> = cmd("HGETALL")
+        .arg(chain_key(chain, CLEANER_STATUS))
+        .query_async::<Vec<(String, String)>>(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+const WEBHOOK_SUBSCRIPTIONS: &str = "webhook:subscriptions";
+const WEBHOOK_CURSOR: &str = "webhook:cursor";
+const WEBHOOK_RETRIES: &str = "webhook:retries";
+const WEBHOOK_STATUS_PREFIX: &str = "webhook:status";
+
+fn webhook_status_key(id: &str) -> String {
+    key(&format!("{}:{}", WEBHOOK_STATUS_PREFIX, id))
+}
+
+pub async fn save_subscription(id: &str, value: String) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("HSET")
+        .arg(&[key(WEBHOOK_SUBSCRIPTIONS), id.to_string(), value])
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_subscription(id: &str) -> Result<Option<String>> {
+    let mut conn = pool()?.get().await?;
+
+    let value: Option<String> = cmd("HGET")
+        .arg(&[key(WEBHOOK_SUBSCRIPTIONS), id.to_string()])
+        .query_async::<Option<String>>(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+pub async fn get_subscriptions() -> Result<Vec<String>> {
+    let mut conn = pool()?.get().await?;
+
+    let value: Vec<String> = cmd("HVALS")
+        .arg(key(WEBHOOK_SUBSCRIPTIONS))
+        .query_async::<Vec<String>>(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+// Returns whether the subscription existed
+pub async fn delete_subscription(id: &str) -> Result<bool> {
+    let mut conn = pool()?.get().await?;
+
+    let value: u64 = cmd("HDEL")
+        .arg(&[key(WEBHOOK_SUBSCRIPTIONS), id.to_string()])
+        .query_async::<u64>(&mut conn)
+        .await?;
+
+    cmd("DEL")
+        .arg(webhook_status_key(id))
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(value > 0)
+}
+
+// Timestamp of the newest transaction of `chain` already handed to the webhook dispatcher
+pub async fn get_webhook_cursor(chain: &Chain) -> Result<Option<String>> {
+    let mut conn = pool()?.get().await?;
+
+    let value: Option<String> = cmd("GET")
+        .arg(chain_key(chain, WEBHOOK_CURSOR))
+        .query_async::<Option<String>>(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+pub async fn set_webhook_cursor(chain: &Chain, cursor: String) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("SET")
+        .arg(&[chain_key(chain, WEBHOOK_CURSOR), cursor])
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+// Deliveries waiting for a retry are kept in a sorted set scored by the time they are due at
+pub async fn schedule_delivery(due: u64, value: String) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("ZADD")
+        .arg(&[key(WEBHOOK_RETRIES), due.to_string(), value])
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+// Removes and returns the deliveries due at `now`. A delivery is only returned to the
+// caller that actually removed it, so concurrent dispatchers never retry the same one
+pub async fn take_due_deliveries(now: u64) -> Result<Vec<String>> {
+    let mut conn = pool()?.get().await?;
+
+    let due: Vec<String> = cmd("ZRANGEBYSCORE")
+        .arg(&[key(WEBHOOK_RETRIES), "-inf".to_string(), now.to_string()])
+        .query_async::<Vec<String>>(&mut conn)
+        .await?;
+
+    let mut taken = vec![];
+    for member in due {
+        let removed: u64 = cmd("ZREM")
+            .arg(&[key(WEBHOOK_RETRIES), member.clone()])
+            .query_async::<u64>(&mut conn)
+            .await?;
+
+        if removed > 0 {
+            taken.push(member);
+        }
+    }
+
+    Ok(taken)
+}
+
+pub async fn incr_webhook_status(id: &str, field: &str) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("HINCRBY")
+        .arg(&[webhook_status_key(id), field.to_string(), "1".to_string()])
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn set_webhook_status(id: &str, field: &str, value: String) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("HSET")
+        .arg(&[webhook_status_key(id), field.to_string(), value])
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_webhook_status(id: &str) -> Result<Vec<(String, String)>> {
+    let mut conn = pool()?.get().await?;
+
+    let value: Vec<(String, String)> = cmd("HGETALL")
+        .arg(webhook_status_key(id))
+        .query_async::<Vec<(String, String)>>(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+const ENS_NAME_PREFIX: &str = "ens:name";
+
+fn ens_name_key(address: &str) -> String {
+    key(&format!("{}:{}", ENS_NAME_PREFIX, address))
+}
+
+// Cached names of `addresses`, an empty string meaning the address has no name
+pub async fn get_ens_names(addresses: &[String]) -> Result<Vec<Option<String>>> {
+    let mut conn = pool()?.get().await?;
+
+    let keys: Vec<String> = addresses.iter().map(|address| ens_name_key(address)).collect();
+    let value: Vec<Option<String>> = cmd("MGET")
+        .arg(&keys)
+        .query_async::<Vec<Option<String>>>(&mut conn)
+        .await?;
+
+    Ok(value)
+}
+
+pub async fn set_ens_name(address: &str, name: String, ttl: u64) -> Result<()> {
+    let mut conn = pool()?.get().await?;
+
+    cmd("SET")
+        .arg(&[ens_name_key(address), name, "EX".to_string(), ttl.to_string()])
+        .query_async::<()>(&mut conn)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use crate::codec::{self, Format};
+use crate::transaction::Transaction;
+use anyhow::Result;
+use serde_json::{Map, Value};
+
+// Version of the records written by this build, stored in their `_v` field
+pub const VERSION: u64 = 2;
+// Records written before they carried a version
+const UNVERSIONED: u64 = 1;
+const VERSION_FIELD: &str = "_v";
+
+// Upgrade of a record from version `from` to `from + 1`, working on its JSON object so that
+// records can be upgraded without the `Transaction` layout they were written with
+pub struct Migration {
+    pub from: u64,
+    pub description: &'static str,
+    apply: fn(&mut Map<String, Value>),
+}
+
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    from: 1,
+    description: "Leave out missing senders and recipients instead of storing null",
+    apply: drop_null_addresses,
+}];
+
+fn drop_null_addresses(record: &mut Map<String, Value>) {
+    for field in ["from", "to"] {
+        if record.get(field) == Some(&Value::Null) {
+            record.remove(field);
+        }
+    }
+}
+
+fn version(record: &Map<String, Value>) -> Result<u64> {
+    match record.get(VERSION_FIELD) {
+        None => Ok(UNVERSIONED),
+        Some(value) => value
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("Invalid record version {}", value)),
+    }
+}
+
+// Upgrades a record one version at a time up to `target`, returning the version it had
+pub fn migrate(record: &mut Map<String, Value>, target: u64) -> Result<u64> {
+    let initial = version(record)?;
+    if initial > target {
+        return Err(anyhow::anyhow!(
+            "Record version {} is newer than version {}",
+            initial,
+            target
+        ));
+    }
+
+    for current in initial..target {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == current)
+            .ok_or_else(|| anyhow::anyhow!("No migration from version {}", current))?;
+
+        (migration.apply)(record);
+        record.insert(VERSION_FIELD.to_string(), Value::from(current + 1));
+    }
+
+    Ok(initial)
+}
+
+fn object(value: Value) -> Result<Map<String, Value>> {
+    match value {
+        Value::Object(record) => Ok(record),
+        other => Err(anyhow::anyhow!("Expected a record, found {}", other)),
+    }
+}
+
+pub fn encode(tx: &Transaction, format: &Format) -> Result<Vec<u8>> {
+    let mut record = object(serde_json::to_value(tx)?)?;
+    record.insert(VERSION_FIELD.to_string(), Value::from(VERSION));
+
+    codec::encode(&record, format)
+}
+
+fn decode_record(value: Value) -> Result<Transaction> {
+    let mut record = object(value)?;
+    migrate(&mut record, VERSION)?;
+
+    Ok(serde_json::from_value(Value::Object(record))?)
+}
+
+// Reads a stored member, whatever the version and format it was written with. Members written
+// before transactions were stored on their own hold a JSON array of the transactions of a block
+pub fn decode(member: &[u8]) -> Result<Vec<Transaction>> {
+    match codec::decode(member)? {
+        Value::Array(records) => records.into_iter().map(decode_record).collect(),
+        record => Ok(vec![decode_record(record)?]),
+    }
+}
+
+// Version of a stored record, along with the record upgraded to `target`. Records newer
+// than `target`, e.g. written by a newer version, are returned as they are
+pub fn upgrade(member: &[u8], target: u64) -> Result<(u64, Map<String, Value>)> {
+    let mut record = object(codec::decode(member)?)?;
+    let initial = version(&record)?;
+    if initial > target {
+        return Ok((initial, record));
+    }
+    migrate(&mut record, target)?;
+
+    Ok((initial, record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{Encoding, Kind, Source};
+
+    #[test]
+    fn test_migrations_are_contiguous() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.from, UNVERSIONED + index as u64, "{}", migration.description);
+        }
+        assert_eq!(UNVERSIONED + MIGRATIONS.len() as u64, VERSION);
+    }
+
+    #[test]
+    fn test_old_payloads() {
+        // Written by the first scanner, one array per block
+        let transactions = decode(br#"[{"h":"0x1","m":"gm","t":1630000000,"from":"0xa","to":null}]"#).unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].hash, "0x1");
+        assert_eq!(transactions[0].to, None);
+
+        // Every field added since, still without a version
+        let transactions = decode(
+            br#"{"h":"0x2","m":"68656c6c6f","t":1700000000,"from":"0xa","to":"0xb","k":"contract_call",
+                "b":18000000,"i":3,"v":"1000","s":false,"gu":21000,"gp":"1000000000","n":7,"c":1.0,
+                "a":"setName(string)[0]","e":"hex","mt":"text/plain","ps":5,"lg":"eng","dup":"0x1",
+                "sp":0.5,"md":"flagged","ln":[{"s":0,"e":11,"d":"example.com","r":"fresh_tld"}],
+                "src":"log","l":4}"#,
+        )
+        .unwrap();
+        let tx = &transactions[0];
+        assert_eq!(tx.kind, Some(Kind::ContractCall));
+        assert_eq!(tx.encoding, Encoding::Hex);
+        assert_eq!(tx.source, Source::Log);
+        assert_eq!(tx.links.len(), 1);
+        assert_eq!(tx.log_index, Some(4));
+
+        // Missing optional fields fall back to their defaults
+        let transactions = decode(br#"{"h":"0x3","m":"gm","t":1700000000}"#).unwrap();
+        assert_eq!(transactions[0].from, None);
+        assert_eq!(transactions[0].encoding, Encoding::Utf8);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tx = Transaction {
+            hash: "0x1".to_string(),
+            message: "gm".to_string(),
+            timestamp: 1700000000,
+            from: Some("0xa".to_string()),
+            ..Default::default()
+        };
+
+        let encoded = encode(&tx, &Format::default()).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&encoded).unwrap(),
+            serde_json::json!({"_v": 2, "h": "0x1", "m": "gm", "t": 1700000000, "from": "0xa"})
+        );
+        assert_eq!(decode(&encoded).unwrap()[0].hash, "0x1");
+
+        // Compact records read back the same
+        let format = Format {
+            encoding: codec::Encoding::MessagePack,
+            compression: Some(3),
+        };
+        let compact = encode(&tx, &format).unwrap();
+        assert!(compact.len() < encoded.len());
+        assert_eq!(decode(&compact).unwrap()[0].from, tx.from);
+
+        assert!(decode(br#"{"_v":99,"h":"0x1","m":"gm","t":1}"#).is_err());
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let (version, record) = upgrade(br#"{"h":"0x1","m":"gm","t":1,"from":null,"to":null}"#, 2).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(
+            Value::Object(record),
+            serde_json::json!({"_v": 2, "h": "0x1", "m": "gm", "t": 1})
+        );
+
+        // Up to date records are left alone
+        let (version, record) = upgrade(br#"{"_v":2,"h":"0x1","m":"gm","t":1}"#, 2).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(record.get("_v"), Some(&Value::from(2)));
+
+        // So are newer ones
+        let (version, record) = upgrade(br#"{"_v":2,"h":"0x1","m":"gm","t":1}"#, 1).unwrap();
+        assert_eq!(version, 2);
+        assert_eq!(record.get("_v"), Some(&Value::from(2)));
+    }
+}
@@ -0,0 +1,380 @@
+use crate::transaction::Transaction;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+// Messages are compared to the ones seen during the last hour
+const DEFAULT_WINDOW: u64 = 3600;
+// Messages remembered at most, the oldest ones are forgotten first
+const MAX_ENTRIES: usize = 50_000;
+// Messages whose fingerprints differ by at most this many bits are near-duplicates. Messages
+// are short, a changed word moves a handful of bits while unrelated ones are 20 or more apart
+const NEAR_DUPLICATE_DISTANCE: u32 = 10;
+// Shorter messages, e.g. "gm", only count as duplicates when they are identical
+const MIN_NEAR_DUPLICATE_LENGTH: usize = 24;
+// Number of earlier copies, and of earlier messages from the same sender, scored as certain spam
+const DUPLICATES_FOR_SPAM: usize = 10;
+const SENDER_MESSAGES_FOR_SPAM: usize = 20;
+
+// What the detector makes of a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict {
+    // Hash of the first transaction seen with the same message, or a nearly identical one
+    pub duplicate_of: Option<String>,
+    // From 0 for a message seen for the first time, to 1 for a flood of copies
+    pub spam_score: f32,
+}
+
+struct Entry {
+    id: String,
+    timestamp: u64,
+    fingerprint: u64,
+    simhash: Option<u64>,
+    root: String,
+    sender: Option<String>,
+}
+
+// Spots repeated messages and chatty senders over a sliding window of recent transactions.
+// Transactions are expected to be scored in the order of their timestamps
+pub struct SpamDetector {
+    window: u64,
+    entries: VecDeque<Entry>,
+    // First transaction of every exact message, along with the number of entries with that message
+    exact: HashMap<u64, (String, usize)>,
+    // Number of entries in the window for every first transaction
+    groups: HashMap<String, usize>,
+    senders: HashMap<String, usize>,
+    // Verdict of every transaction in the window, transactions scored again get the same one
+    verdicts: HashMap<String, Verdict>,
+}
+
+impl Default for SpamDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+// Lowercase words, so that punctuation, case and spacing don't tell copies apart
+fn normalize(message: &str) -> String {
+    message
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Charikar's simhash over character trigrams, similar texts get fingerprints a few bits apart
+fn simhash(text: &str) -> u64 {
+    let chars: Vec<char> = text.chars().collect();
+    let mut weights = [0i32; 64];
+
+    for trigram in chars.windows(3) {
+        let h = hash(trigram);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if h & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |acc, (bit, _)| acc | (1 << bit))
+}
+
+fn decrement<K: Hash + Eq>(counts: &mut HashMap<K, usize>, key: &K) {
+    if let Some(count) = counts.get_mut(key) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(key);
+        }
+    }
+}
+
+impl SpamDetector {
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            entries: VecDeque::new(),
+            exact: HashMap::new(),
+            groups: HashMap::new(),
+            senders: HashMap::new(),
+            verdicts: HashMap::new(),
+        }
+    }
+
+    // Window length from `SPAM_WINDOW`, in seconds
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("SPAM_WINDOW") {
+            Ok(window) => Ok(Self::new(window.parse()?)),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    fn forget(&mut self) {
+        if let Some(entry) = self.entries.pop_front() {
+            if let Some((_, count)) = self.exact.get_mut(&entry.fingerprint) {
+                *count -= 1;
+                if *count == 0 {
+                    self.exact.remove(&entry.fingerprint);
+                }
+            }
+            decrement(&mut self.groups, &entry.root);
+            self.verdicts.remove(&entry.id);
+            if let Some(ref sender) = entry.sender {
+                decrement(&mut self.senders, sender);
+            }
+        }
+    }
+
+    fn expire(&mut self, now: u64) {
+        while let Some(entry) = self.entries.front() {
+            if entry.timestamp + self.window > now && self.entries.len() < MAX_ENTRIES {
+                break;
+            }
+            self.forget();
+        }
+    }
+
+    fn near_duplicate(&self, simhash: u64) -> Option<&str> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.simhash.map(|other| (entry, other)))
+            .find(|(_, other)| (simhash ^ other).count_ones() <= NEAR_DUPLICATE_DISTANCE)
+            .map(|(entry, _)| entry.root.as_str())
+    }
+
+    // Scores `tx` against the messages seen before it, then remembers it. A transaction
+    // already seen, e.g. from a block scanned again, keeps its first verdict
+    pub fn score(&mut self, tx: &Transaction) -> Verdict {
+        if let Some(verdict) = self.verdicts.get(&tx.id()) {
+            return verdict.clone();
+        }
+        self.expire(tx.timestamp);
+
+        let normalized = normalize(&tx.message);
+        let fingerprint = hash(normalized.as_str());
+        let simhash = Some(normalized.chars().count())
+            .filter(|length| *length >= MIN_NEAR_DUPLICATE_LENGTH)
+            .map(|_| simhash(&normalized));
+
+        let root = match self.exact.get(&fingerprint) {
+            Some((root, _)) => Some(root.clone()),
+            None => simhash
+                .and_then(|simhash| self.near_duplicate(simhash))
+                .map(str::to_string),
+        };
+
+        let duplicates = root
+            .as_ref()
+            .and_then(|root| self.groups.get(root))
+            .copied()
+            .unwrap_or(0);
+        let sender = tx.from.as_ref().map(|from| from.to_lowercase());
+        let sender_messages = sender
+            .as_ref()
+            .and_then(|sender| self.senders.get(sender))
+            .copied()
+            .unwrap_or(0);
+
+        // Either signal alone can make a message spam, and both together add up
+        let duplicate_score = (duplicates as f32 / DUPLICATES_FOR_SPAM as f32).min(1.0);
+        let sender_score = (sender_messages as f32 / SENDER_MESSAGES_FOR_SPAM as f32).min(1.0);
+        let spam_score = 1.0 - (1.0 - duplicate_score) * (1.0 - sender_score);
+
+        let entry_root = root.clone().unwrap_or_else(|| tx.hash.clone());
+        self.exact
+            .entry(fingerprint)
+            .or_insert_with(|| (entry_root.clone(), 0))
+            .1 += 1;
+        *self.groups.entry(entry_root.clone()).or_insert(0) += 1;
+        if let Some(ref sender) = sender {
+            *self.senders.entry(sender.clone()).or_insert(0) += 1;
+        }
+        self.entries.push_back(Entry {
+            id: tx.id(),
+            timestamp: tx.timestamp,
+            fingerprint,
+            simhash,
+            root: entry_root,
+            sender,
+        });
+
+        let verdict = Verdict {
+            // A transaction can carry the same message in its input and its logs
+            duplicate_of: root.filter(|root| *root != tx.hash),
+            spam_score,
+        };
+        self.verdicts.insert(tx.id(), verdict.clone());
+        verdict
+    }
+
+    // Remembers a transaction stored by an earlier run, along with the verdict it was stored
+    // with, so that scanning its block again leaves it as it is
+    pub fn remember(&mut self, tx: &Transaction) {
+        self.score(tx);
+        if let Some(spam_score) = tx.spam_score {
+            let verdict = Verdict {
+                duplicate_of: tx.duplicate_of.clone(),
+                spam_score,
+            };
+            self.verdicts.insert(tx.id(), verdict);
+        }
+    }
+}
+
+// Keeps the first transaction of every group of repeated messages, along with the number
+// of other transactions of the group that were dropped
+pub fn collapse(transactions: Vec<Transaction>) -> Vec<(Transaction, u64)> {
+    let mut collapsed: Vec<(Transaction, u64)> = vec![];
+    let mut groups: HashMap<String, usize> = HashMap::new();
+
+    for tx in transactions {
+        let root = tx.duplicate_of.clone().unwrap_or_else(|| tx.hash.clone());
+        match groups.get(&root) {
+            Some(index) => collapsed[*index].1 += 1,
+            None => {
+                groups.insert(root, collapsed.len());
+                collapsed.push((tx, 0));
+            }
+        }
+    }
+
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(hash: &str, from: &str, message: &str, timestamp: u64) -> Transaction {
+        Transaction {
+            hash: hash.to_string(),
+            message: message.to_string(),
+            timestamp,
+            from: Some(from.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut detector = SpamDetector::new(100);
+
+        let first = detector.score(&tx("0x1", "0xa", "Claim your airdrop at example.com now!", 10));
+        assert_eq!(first.duplicate_of, None);
+        assert_eq!(first.spam_score, 0.0);
+
+        // Case, punctuation and spacing don't matter
+        let exact = detector.score(&tx("0x2", "0xb", "claim your AIRDROP at example.com now", 11));
+        assert_eq!(exact.duplicate_of.as_deref(), Some("0x1"));
+        assert!(exact.spam_score > 0.0);
+
+        let near = detector.score(&tx("0x3", "0xc", "Claim your airdrop at example.com now!!! 42", 12));
+        assert_eq!(near.duplicate_of.as_deref(), Some("0x1"));
+        assert!(near.spam_score > exact.spam_score);
+
+        let other = detector.score(&tx("0x4", "0xd", "Happy birthday to my dear friend Alice", 13));
+        assert_eq!(other.duplicate_of, None);
+
+        // Short messages have to be identical
+        detector.score(&tx("0x5", "0xe", "gm", 14));
+        assert_eq!(
+            detector.score(&tx("0x6", "0xf", "gm!", 15)).duplicate_of.as_deref(),
+            Some("0x5")
+        );
+        assert_eq!(detector.score(&tx("0x7", "0xf", "gn", 16)).duplicate_of, None);
+
+        // The same message in the input and the logs of a transaction is no duplicate
+        assert_eq!(
+            detector
+                .score(&tx("0x4", "0xd", "Happy birthday to my dear friend Alice", 17))
+                .duplicate_of,
+            None
+        );
+
+        // Copies are forgotten once out of the window
+        let late = detector.score(&tx("0x8", "0xb", "Claim your airdrop at example.com now!", 200));
+        assert_eq!(late.duplicate_of, None);
+        assert_eq!(late.spam_score, 0.0);
+    }
+
+    #[test]
+    fn test_scored_again() {
+        let mut detector = SpamDetector::new(100);
+
+        let first = detector.score(&tx("0x1", "0xa", "Claim your airdrop at example.com now!", 10));
+        let second = detector.score(&tx("0x2", "0xb", "Claim your airdrop at example.com now!", 11));
+
+        // A block scanned again doesn't count its transactions twice
+        assert_eq!(
+            detector.score(&tx("0x1", "0xa", "Claim your airdrop at example.com now!", 10)),
+            first
+        );
+        assert_eq!(
+            detector.score(&tx("0x2", "0xb", "Claim your airdrop at example.com now!", 11)),
+            second
+        );
+        let third = detector.score(&tx("0x3", "0xc", "Claim your airdrop at example.com now!", 12));
+        assert!((third.spam_score - 0.2).abs() < 1e-6);
+
+        // Transactions stored by an earlier run keep the verdict they were stored with
+        let mut detector = SpamDetector::new(100);
+        let mut stored = tx("0x4", "0xd", "gm", 20);
+        stored.duplicate_of = Some("0x0".to_string());
+        stored.spam_score = Some(0.5);
+        detector.remember(&stored);
+        let verdict = detector.score(&stored);
+        assert_eq!(verdict.duplicate_of.as_deref(), Some("0x0"));
+        assert_eq!(verdict.spam_score, 0.5);
+    }
+
+    #[test]
+    fn test_sender_rate() {
+        let mut detector = SpamDetector::new(1000);
+
+        let mut last = 0.0;
+        for i in 0..SENDER_MESSAGES_FOR_SPAM {
+            let verdict = detector.score(&tx(&format!("0x{}", i), "0xA", &format!("message number {}", i), 10));
+            assert!(verdict.spam_score >= last);
+            last = verdict.spam_score;
+        }
+        assert!(last > 0.9);
+
+        // Addresses are compared case insensitively
+        let verdict = detector.score(&tx("0xff", "0xa", "yet another message", 11));
+        assert_eq!(verdict.spam_score, 1.0);
+
+        let verdict = detector.score(&tx("0xfe", "0xb", "a message from someone else", 11));
+        assert_eq!(verdict.spam_score, 0.0);
+    }
+
+    #[test]
+    fn test_collapse() {
+        let mut first = tx("0x1", "0xa", "spam", 3);
+        let mut second = tx("0x2", "0xb", "spam", 2);
+        let other = tx("0x3", "0xc", "hello", 2);
+        let original = tx("0x0", "0xd", "spam", 1);
+        first.duplicate_of = Some("0x0".to_string());
+        second.duplicate_of = Some("0x0".to_string());
+
+        let collapsed = collapse(vec![first, other, second, original]);
+        let collapsed: Vec<(&str, u64)> = collapsed.iter().map(|(tx, n)| (tx.hash.as_str(), *n)).collect();
+        assert_eq!(collapsed, vec![("0x1", 2), ("0x3", 0)]);
+    }
+}
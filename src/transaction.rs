@@ -1,6 +1,98 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+// Where the message of a transaction was found
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    #[default]
+    Input,
+    Log,
+}
+
+impl Source {
+    pub fn is_input(&self) -> bool {
+        *self == Source::Input
+    }
+}
+
+// What a transaction does, as far as its sender and recipient tell
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    ContractCreation,
+    SelfTransfer,
+    Transfer,
+    ContractCall,
+}
+
+impl Kind {
+    // `to_is_contract` is only called when the recipient is neither missing nor the sender
+    pub fn classify(from: &Option<String>, to: &Option<String>, to_is_contract: impl FnOnce() -> bool) -> Self {
+        match (from, to) {
+            (_, None) => Kind::ContractCreation,
+            (Some(from), Some(to)) if from.eq_ignore_ascii_case(to) => Kind::SelfTransfer,
+            _ if to_is_contract() => Kind::ContractCall,
+            _ => Kind::Transfer,
+        }
+    }
+}
+
+// How the message was encoded in the input data, before being decoded to text
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16,
+    Hex,
+    Base64,
+}
+
+impl Encoding {
+    pub fn is_utf8(&self) -> bool {
+        *self == Encoding::Utf8
+    }
+}
+
+// Outcome of the moderation of a message, unmoderated messages are shown as is
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Moderation {
+    // Shown blurred until clicked
+    Flagged,
+    // Left out of the API
+    Hidden,
+}
+
+// Why a link looks like phishing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkRisk {
+    // The domain, or one of its parents, is in the configured denylist
+    Denylisted,
+    // Internationalized domain name, possibly a lookalike of a well known one
+    Punycode,
+    // TLD favoured by drainer sites
+    FreshTld,
+}
+
+// URL or domain name found in a message, `start` and `end` being byte offsets in the message
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Link {
+    #[serde(rename = "s")]
+    pub start: usize,
+    #[serde(rename = "e")]
+    pub end: usize,
+    #[serde(rename = "d")]
+    pub domain: String,
+    #[serde(rename = "r", default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<LinkRisk>,
+}
+
+// Stored representation of a transaction whose input data could be decoded to text.
+// Field names are kept short since this is what ends up in Redis, the HTTP API maps
+// it to the versioned types in `api`
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Transaction {
     #[serde(rename = "h")]
     pub hash: String,
@@ -8,6 +100,94 @@ pub struct Transaction {
     pub message: String,
     #[serde(rename = "t")]
     pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub to: Option<String>,
+    // Missing for transactions captured before kinds were recorded
+    #[serde(rename = "k", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<Kind>,
+    #[serde(rename = "b", default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    #[serde(rename = "i", default, skip_serializing_if = "Option::is_none")]
+    pub transaction_index: Option<u64>,
+    // Amount of wei transferred, as a decimal string since it does not fit in a u64
+    #[serde(rename = "v", default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    // Whether the transaction succeeded, from its receipt
+    #[serde(rename = "s", default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<bool>,
+    #[serde(rename = "gu", default, skip_serializing_if = "Option::is_none")]
+    pub gas_used: Option<u64>,
+    // Price paid per unit of gas in wei, as a decimal string
+    #[serde(rename = "gp", default, skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<String>,
+    #[serde(rename = "n", default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    // Share of printable characters in the decoded input data, from 0 to 1
+    #[serde(rename = "c", default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    // Function argument the message was decoded from, when the input data is a contract call
+    #[serde(rename = "a", default, skip_serializing_if = "Option::is_none")]
+    pub argument: Option<String>,
+    #[serde(rename = "e", default, skip_serializing_if = "Encoding::is_utf8")]
+    pub encoding: Encoding,
+    // MIME type and decoded size of the payload, when the message is a `data:` URI
+    #[serde(rename = "mt", default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(rename = "ps", default, skip_serializing_if = "Option::is_none")]
+    pub payload_size: Option<u64>,
+    // ISO 639-3 code of the language of the message
+    #[serde(rename = "lg", default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    // Hash of an earlier transaction with the same message, or a nearly identical one
+    #[serde(rename = "dup", default, skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<String>,
+    // Likelihood that the message is spam, from 0 to 1
+    #[serde(rename = "sp", default, skip_serializing_if = "Option::is_none")]
+    pub spam_score: Option<f32>,
+    #[serde(rename = "md", default, skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<Moderation>,
+    #[serde(rename = "ln", default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Link>,
+    #[serde(rename = "src", default, skip_serializing_if = "Source::is_input")]
+    pub source: Source,
+    // Position of the log in the block, for messages found in event logs
+    #[serde(rename = "l", default, skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<u64>,
+}
+
+impl Transaction {
+    // Key of the transaction in the store. A transaction can carry a message in its input
+    // and one in each of its logs
+    pub fn id(&self) -> String {
+        match self.log_index {
+            Some(log_index) => format!("{}:{}", self.hash, log_index),
+            None => self.hash.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let address = |a: &str| Some(a.to_string());
+
+        assert_eq!(Kind::classify(&address("0xa"), &None, || true), Kind::ContractCreation);
+        assert_eq!(
+            Kind::classify(&address("0xAbC"), &address("0xabc"), || true),
+            Kind::SelfTransfer
+        );
+        assert_eq!(
+            Kind::classify(&address("0xa"), &address("0xb"), || true),
+            Kind::ContractCall
+        );
+        assert_eq!(
+            Kind::classify(&address("0xa"), &address("0xb"), || false),
+            Kind::Transfer
+        );
+    }
 }
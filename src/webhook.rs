@@ -0,0 +1,596 @@
+use crate::api::v2;
+use crate::chain::Chain;
+use crate::filter::TransactionFilter;
+use crate::redis;
+use crate::transaction::{Moderation, Transaction};
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+pub const SIGNATURE_HEADER: &str = "X-Interprether-Signature";
+pub const DELIVERY_HEADER: &str = "X-Interprether-Delivery";
+
+const MAX_ATTEMPTS: u32 = 8;
+const BASE_BACKOFF: u64 = 5;
+const MAX_BACKOFF: u64 = 3600;
+const DELIVERY_TIMEOUT: u64 = 10;
+
+fn now() -> u64 {
+    let start = SystemTime::now();
+    let since_the_epoch = start.duration_since(UNIX_EPOCH).expect("Time went backwards");
+    since_the_epoch.as_secs()
+}
+
+fn rand_string(length: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/// Body of a webhook registration
+#[derive(Deserialize, Clone, Debug, ToSchema)]
+pub struct NewSubscription {
+    /// URL receiving the deliveries, must be http or https
+    pub url: String,
+    /// Only transactions matching this filter are delivered, all of them when missing
+    #[serde(default)]
+    pub filter: v2::TransactionFilter,
+    /// Only transactions of these chains, given by name or id, are delivered, those of every
+    /// chain when empty
+    #[serde(default)]
+    pub chains: Vec<String>,
+    /// Key used to sign deliveries, generated when missing
+    pub secret: Option<String>,
+}
+
+/// A registered webhook
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct Subscription {
+    pub id: String,
+    pub url: String,
+    #[schema(value_type = v2::TransactionFilter)]
+    pub filter: TransactionFilter,
+    #[serde(default)]
+    pub chains: Vec<String>,
+    /// Key used to sign deliveries, only returned when the subscription is created
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub secret: String,
+    pub created_at: u64,
+}
+
+impl Subscription {
+    pub fn new(request: NewSubscription) -> Result<Self> {
+        let url = reqwest::Url::parse(&request.url)?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(anyhow::anyhow!("Unsupported URL scheme {}", url.scheme()));
+        }
+
+        // Chains are stored by name, the one they are compared to when dispatching
+        let chains = request
+            .chains
+            .iter()
+            .map(|chain| Chain::parse(&chain.to_lowercase()).map(|chain| chain.name))
+            .collect::<Result<Vec<String>>>()?;
+
+        Ok(Self {
+            id: rand_string(16),
+            url: url.to_string(),
+            filter: request.filter.into(),
+            chains,
+            secret: request.secret.unwrap_or_else(|| rand_string(32)),
+            created_at: now(),
+        })
+    }
+
+    // Messages hidden by moderation are never delivered
+    pub fn wants(&self, chain: &Chain, transaction: &Transaction) -> bool {
+        (self.chains.is_empty() || self.chains.contains(&chain.name))
+            && transaction.moderation != Some(Moderation::Hidden)
+            && self.filter.matches(transaction)
+    }
+
+    // Copy of the subscription that is safe to show to anyone
+    pub fn redacted(&self) -> Self {
+        Self {
+            secret: String::new(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Outcome of the deliveries made for a subscription so far
+#[derive(Serialize, Default, Clone, Debug, PartialEq, ToSchema)]
+pub struct DeliveryStatus {
+    pub delivered: u64,
+    /// Deliveries that are given up on after exhausting their retries
+    pub failed: u64,
+    pub retried: u64,
+    pub last_delivery_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+impl DeliveryStatus {
+    pub fn from_fields(fields: Vec<(String, String)>) -> Self {
+        let mut status = DeliveryStatus::default();
+        for (field, value) in fields {
+            match field.as_str() {
+                "delivered" => status.delivered = value.parse().unwrap_or(0),
+                "failed" => status.failed = value.parse().unwrap_or(0),
+                "retried" => status.retried = value.parse().unwrap_or(0),
+                "last_delivery_at" => status.last_delivery_at = value.parse().ok(),
+                "last_error" => status.last_error = Some(value),
+                _ => {}
+            }
+        }
+
+        status
+    }
+}
+
+// A transaction to be sent to a subscription, possibly after some failed attempts
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Delivery {
+    pub id: String,
+    pub subscription_id: String,
+    // Name of the chain the transaction belongs to
+    #[serde(default)]
+    pub chain: String,
+    pub transaction: Transaction,
+    pub attempts: u32,
+}
+
+impl Delivery {
+    pub fn new(subscription: &Subscription, chain: &Chain, transaction: &Transaction) -> Self {
+        // A transaction can carry several messages, one per event log
+        let id = match transaction.log_index {
+            Some(log_index) => format!("{}-{}-{}-{}", subscription.id, chain.name, transaction.hash, log_index),
+            None => format!("{}-{}-{}", subscription.id, chain.name, transaction.hash),
+        };
+
+        Self {
+            id,
+            subscription_id: subscription.id.clone(),
+            chain: chain.name.clone(),
+            transaction: transaction.clone(),
+            attempts: 0,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Payload {
+    subscription_id: String,
+    chain: String,
+    transaction: v2::Transaction,
+}
+
+// Hex encoded HMAC-SHA256 of `body`, as sent in the signature header
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// Time to wait before the next attempt, doubling at every failure
+pub fn backoff(attempts: u32) -> Duration {
+    let exponent = attempts.saturating_sub(1).min(16);
+    Duration::from_secs(std::cmp::min(BASE_BACKOFF << exponent, MAX_BACKOFF))
+}
+
+// Whether an address can be reached from the internet. Deliveries to other ones would let
+// anyone registering a webhook probe the network of the server, e.g. Redis or the cloud
+// metadata service
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64)
+                || a == 0)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local addresses, fc00::/7
+                    || (first & 0xfe00) == 0xfc00
+                    // Link-local addresses, fe80::/10
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+// Resolves the host of `url` and fails when any of its addresses is not public, unless
+// `allow_private` is set. Returns the address to connect to when the host is a domain name
+async fn resolve(url: &reqwest::Url, allow_private: bool) -> Result<Option<(String, SocketAddr)>> {
+    if allow_private {
+        return Ok(None);
+    }
+
+    let port = url.port_or_known_default().unwrap_or(80);
+    let host = url.host_str().unwrap_or_default();
+    // IPv6 hosts are written in brackets
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        if !is_public(ip) {
+            return Err(anyhow::anyhow!("URL {} points to the non-public address {}", url, ip));
+        }
+
+        return Ok(None);
+    }
+
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|error| anyhow::anyhow!("Could not resolve {}: {}", host, error))?
+        .collect();
+
+    if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+        return Err(anyhow::anyhow!(
+            "URL {} resolves to the non-public address {}",
+            url,
+            address.ip()
+        ));
+    }
+
+    match addresses.first() {
+        Some(address) => Ok(Some((host.to_string(), *address))),
+        None => Err(anyhow::anyhow!("URL {} has no address", url)),
+    }
+}
+
+// Fails when the host of `url` is not public, unless `allow_private` is set
+pub async fn check_url(url: &str, allow_private: bool) -> Result<()> {
+    resolve(&reqwest::Url::parse(url)?, allow_private).await?;
+
+    Ok(())
+}
+
+// Whether webhooks can be sent to loopback and private addresses, from
+// `WEBHOOK_ALLOW_PRIVATE_URLS`, e.g. to reach a receiver on the local network in development
+pub fn allow_private_urls() -> Result<bool> {
+    match std::env::var("WEBHOOK_ALLOW_PRIVATE_URLS") {
+        Ok(allow) => allow
+            .parse()
+            .map_err(|_| anyhow::anyhow!("WEBHOOK_ALLOW_PRIVATE_URLS must be true or false")),
+        Err(_) => Ok(false),
+    }
+}
+
+// Sends the deliveries
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    allow_private_urls: bool,
+}
+
+// Redirects are not followed and proxies are not used, as both would connect to addresses
+// that were not checked
+fn builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(DELIVERY_TIMEOUT))
+        .redirect(reqwest::redirect::Policy::none())
+        .no_proxy()
+}
+
+// Client connecting to `address` whatever `host` resolves to
+fn pinned(host: &str, address: SocketAddr) -> Result<reqwest::Client> {
+    Ok(builder().resolve(host, address).build()?)
+}
+
+impl Client {
+    pub fn new(allow_private_urls: bool) -> Result<Self> {
+        Ok(Self {
+            http: builder().build()?,
+            allow_private_urls,
+        })
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Self::new(allow_private_urls()?)
+    }
+
+    // Client to send to `url` with. The host is resolved once, and the connection made to the
+    // address that was checked, so that a host resolving to another address by then, e.g. by
+    // DNS rebinding, can't reach the internal network
+    async fn for_url(&self, url: &reqwest::Url) -> Result<reqwest::Client> {
+        match resolve(url, self.allow_private_urls).await? {
+            Some((host, address)) => pinned(&host, address),
+            None => Ok(self.http.clone()),
+        }
+    }
+}
+
+// Sends a single signed POST, failing on anything but a 2xx response. The URL is checked
+// again, as the addresses its host resolves to can have changed since the subscription
+pub async fn deliver(client: &Client, subscription: &Subscription, delivery: &Delivery) -> Result<()> {
+    let url = reqwest::Url::parse(&subscription.url)?;
+    let http = client.for_url(&url).await?;
+
+    let payload = Payload {
+        subscription_id: subscription.id.clone(),
+        chain: delivery.chain.clone(),
+        transaction: (&delivery.transaction).into(),
+    };
+    let body = serde_json::to_vec(&payload)?;
+
+    let response = http
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, sign(&subscription.secret, &body))
+        .header(DELIVERY_HEADER, &delivery.id)
+        .body(body)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Receiver answered with {}", response.status()));
+    }
+
+    Ok(())
+}
+
+// Delivers and records the outcome, scheduling a retry on failure
+pub async fn dispatch(client: &Client, subscription: &Subscription, mut delivery: Delivery) -> Result<()> {
+    delivery.attempts += 1;
+
+    match deliver(client, subscription, &delivery).await {
+        Ok(()) => {
+            redis::incr_webhook_status(&subscription.id, "delivered").await?;
+            redis::set_webhook_status(&subscription.id, "last_delivery_at", now().to_string()).await?;
+        }
+        Err(error) => {
+            log::warn!(
+                "Delivery {} failed at attempt {}: {:?}",
+                delivery.id,
+                delivery.attempts,
+                error
+            );
+            redis::set_webhook_status(&subscription.id, "last_error", error.to_string()).await?;
+
+            if delivery.attempts >= MAX_ATTEMPTS {
+                redis::incr_webhook_status(&subscription.id, "failed").await?;
+            } else {
+                redis::incr_webhook_status(&subscription.id, "retried").await?;
+
+                let due = now() + backoff(delivery.attempts).as_secs();
+                redis::schedule_delivery(due, serde_json::to_string(&delivery)?).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use warp::Filter;
+
+    type Received = Arc<Mutex<Vec<(String, String, String)>>>;
+
+    // Spawns a local receiver answering with `status` and recording signature, delivery id and body
+    async fn receiver(status: u16) -> (String, Received) {
+        let received: Received = Arc::new(Mutex::new(vec![]));
+        let store = received.clone();
+
+        let route = warp::post()
+            .and(warp::header::<String>(SIGNATURE_HEADER))
+            .and(warp::header::<String>(DELIVERY_HEADER))
+            .and(warp::body::bytes())
+            .map(
+                move |signature: String, delivery: String, body: warp::hyper::body::Bytes| {
+                    let body = String::from_utf8(body.to_vec()).unwrap();
+                    store.lock().unwrap().push((signature, delivery, body));
+                    warp::reply::with_status("", warp::http::StatusCode::from_u16(status).unwrap())
+                },
+            );
+
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        (format!("http://{}/hook", address), received)
+    }
+
+    fn subscription(url: String) -> Subscription {
+        Subscription::new(NewSubscription {
+            url,
+            filter: v2::TransactionFilter::default(),
+            chains: vec![],
+            secret: Some("secret".to_string()),
+        })
+        .unwrap()
+    }
+
+    fn transaction() -> Transaction {
+        Transaction {
+            hash: "0x1".to_string(),
+            message: "hello!".to_string(),
+            timestamp: 1630000000,
+            from: Some("0x2".to_string()),
+            to: Some("0x3".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deliver_signs_payload() {
+        let (url, received) = receiver(200).await;
+        let subscription = subscription(url);
+        let delivery = Delivery::new(&subscription, &Chain::default(), &transaction());
+
+        deliver(&Client::new(true).unwrap(), &subscription, &delivery)
+            .await
+            .unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+
+        let (signature, delivery_id, body) = &received[0];
+        assert_eq!(signature, &sign("secret", body.as_bytes()));
+        assert_eq!(delivery_id, &delivery.id);
+
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["subscription_id"], subscription.id.as_str());
+        assert_eq!(body["chain"], "mainnet");
+        assert_eq!(body["transaction"]["hash"], "0x1");
+        assert_eq!(body["transaction"]["message"], "hello!");
+    }
+
+    #[tokio::test]
+    async fn test_deliver_fails_on_error_status() {
+        let (url, received) = receiver(500).await;
+        let subscription = subscription(url);
+        let delivery = Delivery::new(&subscription, &Chain::default(), &transaction());
+
+        assert!(deliver(&Client::new(true).unwrap(), &subscription, &delivery)
+            .await
+            .is_err());
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deliver_rejects_private_url() {
+        let (url, received) = receiver(200).await;
+        let subscription = subscription(url);
+        let delivery = Delivery::new(&subscription, &Chain::default(), &transaction());
+
+        assert!(deliver(&Client::new(false).unwrap(), &subscription, &delivery)
+            .await
+            .is_err());
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_client() {
+        let (url, received) = receiver(200).await;
+        let address: SocketAddr = url
+            .trim_start_matches("http://")
+            .trim_end_matches("/hook")
+            .parse()
+            .unwrap();
+
+        // The host doesn't resolve, the connection goes to the pinned address anyway
+        let pinned_url = format!("http://receiver.invalid:{}/hook", address.port());
+        pinned("receiver.invalid", address)
+            .unwrap()
+            .post(&pinned_url)
+            .header(SIGNATURE_HEADER, "")
+            .header(DELIVERY_HEADER, "")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(received.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        for url in [
+            "http://localhost/hook",
+            "http://127.0.0.1:6379",
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.0.0.1/hook",
+            "http://192.168.1.1/hook",
+            "http://0.0.0.0/hook",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+        ] {
+            assert!(check_url(url, false).await.is_err(), "{}", url);
+            assert!(check_url(url, true).await.is_ok(), "{}", url);
+        }
+
+        assert!(check_url("https://1.1.1.1/hook", false).await.is_ok());
+        assert!(check_url("https://[2606:4700:4700::1111]/hook", false).await.is_ok());
+    }
+
+    #[test]
+    fn test_sign() {
+        // Test vector from RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(5));
+        assert_eq!(backoff(2), Duration::from_secs(10));
+        assert_eq!(backoff(3), Duration::from_secs(20));
+        assert_eq!(backoff(100), Duration::from_secs(MAX_BACKOFF));
+    }
+
+    #[test]
+    fn test_subscription_validates_url() {
+        let request = |url: &str| NewSubscription {
+            url: url.to_string(),
+            filter: v2::TransactionFilter::default(),
+            chains: vec![],
+            secret: None,
+        };
+
+        assert!(Subscription::new(request("https://example.com/hook")).is_ok());
+        assert!(Subscription::new(request("ftp://example.com/hook")).is_err());
+        assert!(Subscription::new(request("not a url")).is_err());
+        assert_eq!(
+            Subscription::new(request("http://example.com")).unwrap().secret.len(),
+            32
+        );
+    }
+
+    #[test]
+    fn test_subscription_validates_chains() {
+        let request = |chains: &[&str]| NewSubscription {
+            url: "https://example.com/hook".to_string(),
+            filter: v2::TransactionFilter::default(),
+            chains: chains.iter().map(|chain| chain.to_string()).collect(),
+            secret: None,
+        };
+
+        assert_eq!(
+            Subscription::new(request(&["Base", "1", " mainnet "])).unwrap().chains,
+            vec!["base", "mainnet", "mainnet"]
+        );
+        assert!(Subscription::new(request(&["mainnet", "solana"])).is_err());
+    }
+
+    #[test]
+    fn test_subscription_chains() {
+        let mut subscription = subscription("https://example.com/hook".to_string());
+        let base = Chain::parse("base").unwrap();
+
+        assert!(subscription.wants(&Chain::default(), &transaction()));
+        assert!(subscription.wants(&base, &transaction()));
+
+        subscription.chains = vec!["base".to_string()];
+        assert!(!subscription.wants(&Chain::default(), &transaction()));
+        assert!(subscription.wants(&base, &transaction()));
+
+        let hidden = Transaction {
+            moderation: Some(Moderation::Hidden),
+            ..transaction()
+        };
+        assert!(!subscription.wants(&base, &hidden));
+    }
+}